@@ -1,9 +1,9 @@
 /**
- *  These two macros define when we want our fuzzer to start 
+ *  One kernel serves every fuzzer strategy
  *
  *  Sync-exit mode we tell our fuzzer explicitly when we want to start and stop
- *  Breakpoint mode no explictity instead it enter a infinite loop so the CPU detects and kills the 
- *  Harness
+ *  Breakpoint and low level mode no explictity instead it enter a infinite loop so the CPU detects
+ *  and kills the Harness
  *
  */
 
@@ -12,15 +12,71 @@
 // leading to a lot of issues so resolved by altering name for now
 typedef unsigned int my_uint32_t;
 
-#ifdef TARGET_SYNC_EXIT
-  #include "libafl_qemu.h"
-#endif /* if def TARGET_SYNC_EXIT */
+#include "libafl_qemu.h"
+#include "nyx_api.h"
+
+/**
+ *  Set to 1 by the fuzzer when it runs with --strategy sync-exit
+ *  Lives in .data because startup.c zeroes .bss after the fuzzer wrote it
+ */
+__attribute__((section(".data"))) volatile my_uint32_t FUZZ_SYNC_EXIT = 0;
+
+/**
+ *  Set to 1 by the fuzzer when it runs with --strategy nyx
+ *  main then runs the harness as a Nyx agent instead
+ */
+__attribute__((section(".data"))) volatile my_uint32_t FUZZ_NYX = 0;
+
+/**
+ *  Nyx hypercall on ARM, the fuzzer sets a breakpoint here and answers
+ *  the hypercall before returning to the caller
+ */
+uintptr_t __attribute__((noinline)) kAFL_hypercall(uintptr_t p1, uintptr_t p2) {
+  __asm volatile("" : : "r"(p1), "r"(p2) : "memory");
+  return 0;
+}
+
+/**
+ *  Bumped by the Timer 0 interrupt, nothing on the board raises it
+ *  only the fuzzer pends it through an --irqs schedule on the mps2 boards
+ */
+#if defined(__ARM_ARCH_7M__) || defined(__ARM_ARCH_7EM__)
+#define TIMER0_IRQn 8
+#define NVIC_ISER   ((volatile my_uint32_t*)0xE000E100)
+#endif
+
+volatile my_uint32_t TICKS = 0;
+
+/**
+ *  First UART of the board, the fuzzer captures what goes out of it
+ *  for every run and matches it against --serial-objective
+ */
+#if defined(__ARM_ARCH_7M__) || defined(__ARM_ARCH_7EM__)
+#define UART0_DATA      ((volatile my_uint32_t*)0x40004000)
+#define UART0_CTRL      ((volatile my_uint32_t*)0x40004008)
+#define UART0_CTRL_INIT 0x1   // TX enable
+#elif defined(__aarch64__)
+#define UART0_DATA      ((volatile my_uint32_t*)0x09000000)
+#define UART0_CTRL      ((volatile my_uint32_t*)0x09000030)
+#define UART0_CTRL_INIT 0x101 // UARTEN | TXE
+#elif defined(__riscv)
+#define UART0_DATA      ((volatile unsigned char*)0x10000000)
+#endif
+
+static void uart_puts(const char* s) {
+#ifdef UART0_DATA
+  while (*s)
+    *UART0_DATA = *s++;
+#endif
+}
+
+void TIMER0_Handler(void) {
+  TICKS++;
+}
 
-#ifndef TARGET_SYNC_EXIT
 int __attribute__((noinline)) BREAKPOINT() {
   for (;;) {}
 }
-#endif /* if not def TARGET_SYNC_EXIT */
 
 /**  Standard libFuzzer style entry for one test input
  *   simulates how you would fuzz a baremetal target
@@ -30,15 +86,17 @@ int __attribute__((noinline)) BREAKPOINT() {
  *     - Timeouts
  */
 int LLVMFuzzerTestOneInput(my_uint32_t* data, my_uint32_t size) {
-  #ifdef TARGET_SYNC_EXIT
-  // Tells QEMU fuzzer to start collecting coverage from address Data with length Size
-   libafl_qemu_start_phys((void*)data, size);
-  #endif /* ifdef TARGET_SYNC_EXIT */
+  // Tells QEMU fuzzer to start collecting coverage from address Data with length Size, in bytes
+  if (FUZZ_SYNC_EXIT)
+    libafl_qemu_start_phys((void*)data, size * sizeof(my_uint32_t));
 
   // Timeout Trigger
   if (data[3] == 0)
     while (1) {}
 
+  // Read before the sort and trusted after it, an interrupt in between breaks that
+  my_uint32_t ticks = TICKS;
+
   // Swap each 4 bytes
   for (int i = 0; i < size; ++i) {
     for (int j = i + 1; j < size; ++j) {
@@ -53,15 +111,26 @@ int LLVMFuzzerTestOneInput(my_uint32_t* data, my_uint32_t size) {
       }
     }
   }
-  #ifdef TARGET_SYNC_EXIT
-    // Stops QEMU fuzzer coverage
+  // Race with the interrupt handler, only reachable with an interrupt during the sort
+  if (TICKS != ticks && data[0] > 900)
+    return *(volatile my_uint32_t*)0xFFFFFFF0;
+
+  // Only reported over the UART, the firmware carries on like nothing happened
+  if (size > 1 && data[0] == data[1] + 1)
+    uart_puts("ASSERT failed: neighbours after sort\n");
+
+  // Stops QEMU fuzzer coverage
+  if (FUZZ_SYNC_EXIT) {
     libafl_qemu_end(LIBAFL_QEMU_END_OK);
-  #else 
-    // force a timeout
-    return BREAKPOINT();
-  #endif /* ifdef TARGET_SYNC_EXIT */
-  
-  return 1;
+    return 1;
+  }
+
+  // The Nyx agent releases the payload itself
+  if (FUZZ_NYX)
+    return 1;
+
+  // force a timeout
+  return BREAKPOINT();
 }
 
 my_uint32_t FUZZ_INPUT[] = {
@@ -71,6 +140,34 @@ my_uint32_t FUZZ_INPUT[] = {
   125, 324, 560, 852, 928, 910, 790, 853, 345, 234, 586,
 };
 
+// Payload buffer of the Nyx agent, the size followed by as many words as FUZZ_INPUT holds
+__attribute__((aligned(4))) uint8_t NYX_PAYLOAD[sizeof(int32_t) + sizeof(FUZZ_INPUT)];
+
+/**  Same harness driven through the Nyx hypercall API
+ *   the fuzzer snapshots at ACQUIRE and fills in the payload
+ *   every run ends at RELEASE
+ */
+static void nyx_agent() {
+  kAFL_payload* payload = (kAFL_payload*)NYX_PAYLOAD;
+
+  kAFL_hypercall(HYPERCALL_KAFL_GET_PAYLOAD, (uintptr_t)payload);
+  kAFL_hypercall(HYPERCALL_KAFL_ACQUIRE, 0);
+
+  LLVMFuzzerTestOneInput((my_uint32_t*)payload->data, payload->size / sizeof(my_uint32_t));
+
+  kAFL_hypercall(HYPERCALL_KAFL_RELEASE, 0);
+}
+
 int main() {
-  LLVMFuzzerTestOneInput(FUZZ_INPUT, 50);
+#ifdef NVIC_ISER
+  *NVIC_ISER = 1 << TIMER0_IRQn;
+#endif
+#ifdef UART0_CTRL
+  *UART0_CTRL = UART0_CTRL_INIT;
+#endif
+
+  if (FUZZ_NYX)
+    nyx_agent();
+  else
+    LLVMFuzzerTestOneInput(FUZZ_INPUT, 50);
 }
@@ -119,6 +119,10 @@ static inline uint64_t kAFL_hypercall(uint64_t p1, uint64_t p2) {
   asm volatile("vmcall" : "=a"(nr) : "a"(nr), "b"(p1), "c"(p2));
   return nr;
 }
+#elif defined(__arm__) || defined(__aarch64__) || defined(__riscv)
+/* No vmcall outside x86, the fuzzer breaks on this function and returns the result in the first argument register
+   the arguments are register sized so pointers survive on aarch64 and riscv64 */
+uintptr_t kAFL_hypercall(uintptr_t p1, uintptr_t p2);
 #endif
 
 static void habort(char *msg) __attribute__((unused));
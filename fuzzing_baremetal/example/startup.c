@@ -35,14 +35,39 @@ __attribute__((optimize("O0"))) __attribute__((naked)) void Reset_Handler(
   _start();
 }
 
+/**
+ * Fault handlers, weak so a firmware can bring its own
+ * The fuzzer sets breakpoints on them to classify crashes
+ */
+__attribute__((weak, noinline)) void HardFault_Handler(void) {
+  for (;;) {}
+}
+
+__attribute__((weak, noinline)) void MemManage_Handler(void) {
+  for (;;) {}
+}
+
+__attribute__((weak, noinline)) void BusFault_Handler(void) {
+  for (;;) {}
+}
+
+__attribute__((weak, noinline)) void UsageFault_Handler(void) {
+  for (;;) {}
+}
+
+/**
+ * Interrupt handlers, the fuzzer pends them with --irqs
+ */
+__attribute__((weak, noinline)) void TIMER0_Handler(void) {}
+
 const uint32_t *isr_vector[] __attribute__((section(".isr_vector"))) = {
     (uint32_t *)&_estack,
     (uint32_t *)&Reset_Handler, /* Reset                -15 */
     0,                          /* NMI_Handler          -14 */
-    0,                          /* HardFault_Handler    -13 */
-    0,                          /* MemManage_Handler    -12 */
-    0,                          /* BusFault_Handler     -11 */
-    0,                          /* UsageFault_Handler   -10 */
+    (uint32_t *)&HardFault_Handler,  /* HardFault_Handler    -13 */
+    (uint32_t *)&MemManage_Handler,  /* MemManage_Handler    -12 */
+    (uint32_t *)&BusFault_Handler,   /* BusFault_Handler     -11 */
+    (uint32_t *)&UsageFault_Handler, /* UsageFault_Handler   -10 */
     0,                          /* reserved */
     0,                          /* reserved */
     0,                          /* reserved */
@@ -60,7 +85,7 @@ const uint32_t *isr_vector[] __attribute__((section(".isr_vector"))) = {
     0,                          /* uart 2 transmit */
     0,                          /* GPIO 0 combined interrupt */
     0,                          /* GPIO 2 combined interrupt */
-    0,                          /* Timer 0 */
+    (uint32_t *)&TIMER0_Handler,     /* Timer 0 */
     0,                          /* Timer 1 */
     0,                          /* Dial Timer */
     0,                          /* SPI0 SPI1 */
@@ -0,0 +1,273 @@
+use std::fmt;
+use libafl_qemu::{GuestAddr, Regs};
+
+#[cfg(any(
+    all(feature = "arm", feature = "aarch64"),
+    all(feature = "arm", feature = "riscv32"),
+    all(feature = "aarch64", feature = "riscv32"),
+))]
+compile_error!("Only one of the arm, aarch64 and riscv32 features can be enabled");
+
+#[cfg(not(any(feature = "arm", feature = "aarch64", feature = "riscv32")))]
+compile_error!("Enable one of the arm, aarch64 and riscv32 features");
+
+/*
+ * Everything the fuzzer and the example firmware need to know about a board
+ *
+ * libafl_qemu links a single QEMU target, so the architecture is picked with a cargo
+ * feature and only the boards of that architecture can be picked with --board
+ *
+ * @var name          - what --board takes
+ * @var machine       - QEMU -machine
+ * @var cpu           - QEMU -cpu, also what the example firmware is compiled for
+ * @var qemu_args     - anything else the board needs on the QEMU command line
+ * @var start_symbol  - first code of the firmware, faulting runs end on it when there is no vector table
+ * @var pc            - register holding the program counter
+ * @var cortex_m      - has the vector table, fault status registers and NVIC of ARMv7-M
+ * */
+#[derive(Debug)]
+pub struct Board {
+    pub name: &'static str,
+    pub machine: &'static str,
+    pub cpu: &'static str,
+    pub qemu_args: &'static [&'static str],
+    pub start_symbol: &'static str,
+    pub pc: Regs,
+    pub cortex_m: bool,
+}
+
+impl Board {
+    // The first board of this build, used without --board
+    pub fn default_board() -> &'static Board {
+        &BOARDS[0]
+    }
+
+    // Profile for --board, panics with the boards of this build if there is none
+    pub fn find(name: &str) -> &'static Board {
+        BOARDS.iter().find(|board| board.name == name).unwrap_or_else(|| {
+            let names: Vec<_> = BOARDS.iter().map(|board| board.name).collect();
+            panic!(
+                "Unknown board '{name}', this build supports {} (other architectures need the arm, aarch64 or riscv32 feature)",
+                names.join(", ")
+            )
+        })
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} on {})", self.name, self.cpu, self.machine)
+    }
+}
+
+/*
+ * ARMv7-M boards of the MPS2 family, they share the memory map of the an385
+ *
+ * The first board is the default
+ * */
+#[cfg(feature = "arm")]
+pub const BOARDS: &[Board] = &[
+    Board {
+        name: "mps2-an385",
+        machine: "mps2-an385",
+        cpu: "cortex-m3",
+        qemu_args: &[],
+        start_symbol: "Reset_Handler",
+        pc: Regs::Pc,
+        cortex_m: true,
+    },
+    Board {
+        name: "mps2-an386",
+        machine: "mps2-an386",
+        cpu: "cortex-m4",
+        qemu_args: &[],
+        start_symbol: "Reset_Handler",
+        pc: Regs::Pc,
+        cortex_m: true,
+    },
+    Board {
+        name: "mps2-an500",
+        machine: "mps2-an500",
+        cpu: "cortex-m7",
+        qemu_args: &[],
+        start_symbol: "Reset_Handler",
+        pc: Regs::Pc,
+        cortex_m: true,
+    },
+];
+
+// QEMU virt board with RAM at 0x40000000, the firmware starts in EL1
+#[cfg(feature = "aarch64")]
+pub const BOARDS: &[Board] = &[Board {
+    name: "virt-aarch64",
+    machine: "virt",
+    cpu: "cortex-a53",
+    qemu_args: &[],
+    start_symbol: "_start",
+    pc: Regs::Pc,
+    cortex_m: false,
+}];
+
+// QEMU virt board with RAM at 0x80000000, no OpenSBI so the firmware starts in M-mode
+#[cfg(feature = "riscv32")]
+pub const BOARDS: &[Board] = &[Board {
+    name: "virt-riscv32",
+    machine: "virt",
+    cpu: "rv32",
+    qemu_args: &["-bios", "none"],
+    start_symbol: "_start",
+    pc: Regs::Pc,
+    cortex_m: false,
+}];
+
+/*
+ * Registers of the calling convention
+ *
+ * ARG_REGS   - first two arguments of a call, the first one also carries the result
+ * RETURN_REG - return address of a call with its name in the crash report
+ * REGISTERS  - registers in the crash report, in the order they are printed
+ * */
+#[cfg(feature = "arm")]
+pub const ARG_REGS: [Regs; 2] = [Regs::R0, Regs::R1];
+#[cfg(feature = "arm")]
+pub const RETURN_REG: (&str, Regs) = ("lr", Regs::Lr);
+#[cfg(feature = "arm")]
+pub const REGISTERS: &[(&str, Regs)] = &[
+    ("r0", Regs::R0),
+    ("r1", Regs::R1),
+    ("r2", Regs::R2),
+    ("r3", Regs::R3),
+    ("r4", Regs::R4),
+    ("r5", Regs::R5),
+    ("r6", Regs::R6),
+    ("r7", Regs::R7),
+    ("r8", Regs::R8),
+    ("r9", Regs::R9),
+    ("r10", Regs::R10),
+    ("r11", Regs::R11),
+    ("r12", Regs::R12),
+    ("sp", Regs::Sp),
+    ("lr", Regs::Lr),
+    ("pc", Regs::Pc),
+    ("xpsr", Regs::Cpsr),
+];
+
+#[cfg(feature = "aarch64")]
+pub const ARG_REGS: [Regs; 2] = [Regs::X0, Regs::X1];
+#[cfg(feature = "aarch64")]
+pub const RETURN_REG: (&str, Regs) = ("lr", Regs::X30);
+#[cfg(feature = "aarch64")]
+pub const REGISTERS: &[(&str, Regs)] = &[
+    ("x0", Regs::X0),
+    ("x1", Regs::X1),
+    ("x2", Regs::X2),
+    ("x3", Regs::X3),
+    ("x4", Regs::X4),
+    ("x5", Regs::X5),
+    ("x6", Regs::X6),
+    ("x7", Regs::X7),
+    ("x8", Regs::X8),
+    ("x19", Regs::X19),
+    ("x20", Regs::X20),
+    ("x21", Regs::X21),
+    ("x22", Regs::X22),
+    ("x23", Regs::X23),
+    ("x24", Regs::X24),
+    ("x25", Regs::X25),
+    ("x26", Regs::X26),
+    ("x27", Regs::X27),
+    ("x28", Regs::X28),
+    ("fp", Regs::X29),
+    ("lr", Regs::X30),
+    ("sp", Regs::Sp),
+    ("pc", Regs::Pc),
+    ("pstate", Regs::Pstate),
+];
+
+#[cfg(feature = "riscv32")]
+pub const ARG_REGS: [Regs; 2] = [Regs::A0, Regs::A1];
+#[cfg(feature = "riscv32")]
+pub const RETURN_REG: (&str, Regs) = ("ra", Regs::Ra);
+#[cfg(feature = "riscv32")]
+pub const REGISTERS: &[(&str, Regs)] = &[
+    ("a0", Regs::A0),
+    ("a1", Regs::A1),
+    ("a2", Regs::A2),
+    ("a3", Regs::A3),
+    ("a4", Regs::A4),
+    ("a5", Regs::A5),
+    ("a6", Regs::A6),
+    ("a7", Regs::A7),
+    ("ra", Regs::Ra),
+    ("sp", Regs::Sp),
+    ("gp", Regs::Gp),
+    ("tp", Regs::Tp),
+    ("pc", Regs::Pc),
+];
+
+/*
+ * Semihosting call of the architecture
+ *
+ * SEMIHOSTING_TRAP - instruction sequence the firmware makes a call with
+ * SEMIHOSTING_STEP - alignment of the sequence in the code
+ * SEMIHOSTING_CALL - offset of the instruction in the sequence the call happens on and its size
+ * */
+#[cfg(feature = "arm")]
+pub const SEMIHOSTING_TRAP: &[u8] = &[0xAB, 0xBE]; // bkpt 0xab
+#[cfg(feature = "arm")]
+pub const SEMIHOSTING_STEP: usize = 2;
+#[cfg(feature = "arm")]
+pub const SEMIHOSTING_CALL: (GuestAddr, GuestAddr) = (0, 2);
+
+#[cfg(feature = "aarch64")]
+pub const SEMIHOSTING_TRAP: &[u8] = &[0x00, 0x00, 0x5E, 0xD4]; // hlt #0xf000
+#[cfg(feature = "aarch64")]
+pub const SEMIHOSTING_STEP: usize = 4;
+#[cfg(feature = "aarch64")]
+pub const SEMIHOSTING_CALL: (GuestAddr, GuestAddr) = (0, 4);
+
+// slli x0, x0, 0x1f; ebreak; srai x0, x0, 7
+#[cfg(feature = "riscv32")]
+pub const SEMIHOSTING_TRAP: &[u8] = &[
+    0x13, 0x10, 0xF0, 0x01,
+    0x73, 0x00, 0x10, 0x00,
+    0x13, 0x50, 0x70, 0x40,
+];
+#[cfg(feature = "riscv32")]
+pub const SEMIHOSTING_STEP: usize = 2;
+#[cfg(feature = "riscv32")]
+pub const SEMIHOSTING_CALL: (GuestAddr, GuestAddr) = (4, 4);
+
+/*
+ * Instructions in a block of code, for instruction counting
+ *
+ * Thumb-2 instructions start with 0b11101, 0b11110 or 0b11111 and take 4 bytes, compressed
+ * RISC-V ones do not have both low bits set and take 2
+ * */
+#[cfg(feature = "arm")]
+pub fn count_insns(code: &[u8]) -> u64 {
+    let mut insns = 0;
+    let mut offset = 0;
+    while offset + 1 < code.len() {
+        let hw = u16::from_le_bytes([code[offset], code[offset + 1]]);
+        offset += if hw >> 11 >= 0b11101 { 4 } else { 2 };
+        insns += 1;
+    }
+    insns
+}
+
+#[cfg(feature = "aarch64")]
+pub fn count_insns(code: &[u8]) -> u64 {
+    (code.len() / 4) as u64
+}
+
+#[cfg(feature = "riscv32")]
+pub fn count_insns(code: &[u8]) -> u64 {
+    let mut insns = 0;
+    let mut offset = 0;
+    while offset < code.len() {
+        offset += if code[offset] & 0b11 == 0b11 { 4 } else { 2 };
+        insns += 1;
+    }
+    insns
+}
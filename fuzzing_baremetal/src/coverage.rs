@@ -0,0 +1,172 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Write as _, fs, path::{Path, PathBuf}, sync::Mutex};
+use addr2line::Loader;
+use libafl::Error;
+use libafl_qemu::{elf::EasyElf, GuestAddr, GuestUsize, Qemu};
+use libafl_targets::drcov::{DrCovBasicBlock, DrCovWriter};
+use rangemap::RangeMap;
+
+use crate::report::Symbolizer;
+
+// Program header type of a loadable segment
+const PT_LOAD: u32 = 1;
+
+// Thumb instructions are at least 2 bytes, every halfword of the code is looked up in DWARF
+const INSN_ALIGN: usize = 2;
+
+/*
+ * Filled by the block hooks of QEMU
+ *
+ * Translated blocks are cached across runs so their lengths are kept for the whole
+ * replay, the executed blocks are cleared before every testcase
+ * */
+static BLOCK_LENS: Mutex<BTreeMap<GuestAddr, GuestUsize>> = Mutex::new(BTreeMap::new());
+static BLOCK_HITS: Mutex<BTreeSet<GuestAddr>> = Mutex::new(BTreeSet::new());
+
+// The id of a block is its start address, handed to the exec hook every time it runs
+unsafe extern "C" fn gen_block(_data: u64, pc: GuestAddr) -> u64 {
+    pc as u64
+}
+
+unsafe extern "C" fn post_gen_block(_data: u64, pc: GuestAddr, len: GuestUsize) {
+    BLOCK_LENS.lock().unwrap().insert(pc, len);
+}
+
+unsafe extern "C" fn exec_block(_data: u64, id: u64) {
+    BLOCK_HITS.lock().unwrap().insert(id as GuestAddr);
+}
+
+/*
+ * Block coverage of a replayed corpus
+ *
+ * Every testcase gets its own drcov file for lighthouse or bncov, the lcov report over
+ * the whole corpus maps the blocks onto source lines through the DWARF info of the kernel
+ *
+ * @var out_dir    - where the drcov files and coverage.info go
+ * @var kernel     - firmware image, read again for its line table
+ * @var module     - address range of the kernel code as drcov wants it
+ * @var symbolizer - functions of the kernel, every line inside them counts as instrumented
+ * @var blocks     - how many testcases executed each block
+ * */
+pub struct BlockCoverage {
+    out_dir: PathBuf,
+    kernel: PathBuf,
+    module: RangeMap<u64, (u16, String)>,
+    symbolizer: Symbolizer,
+    blocks: BTreeMap<GuestAddr, u64>,
+}
+
+impl BlockCoverage {
+    pub fn new(out_dir: &Path, kernel: &Path, elf: &EasyElf) -> Self {
+        fs::create_dir_all(out_dir)
+            .unwrap_or_else(|e| panic!("Failed to create {}: {e}", out_dir.display()));
+
+        // The firmware is one module, spanning every executable segment
+        let (start, end) = elf
+            .goblin()
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == PT_LOAD && ph.is_executable())
+            .fold((u64::MAX, 0), |(start, end), ph| {
+                (start.min(ph.p_vaddr), end.max(ph.p_vaddr + ph.p_memsz))
+            });
+        assert!(start < end, "No executable segment in {}", kernel.display());
+
+        let mut module = RangeMap::new();
+        module.insert(start..end, (0, kernel.display().to_string()));
+
+        Self {
+            out_dir: out_dir.to_path_buf(),
+            kernel: kernel.to_path_buf(),
+            module,
+            symbolizer: Symbolizer::new(elf),
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    // Has to happen before the firmware runs, blocks translated earlier are never seen
+    pub fn install(qemu: Qemu) {
+        qemu.hooks().add_block_hooks(0u64, Some(gen_block), Some(post_gen_block), Some(exec_block));
+    }
+
+    pub fn start_testcase(&self) {
+        BLOCK_HITS.lock().unwrap().clear();
+    }
+
+    // Writes <out_dir>/<name>.drcov for the testcase that just ran, returns its block count
+    pub fn finish_testcase(&mut self, file: &Path) -> Result<usize, Error> {
+        let hits = std::mem::take(&mut *BLOCK_HITS.lock().unwrap());
+        let lens = BLOCK_LENS.lock().unwrap();
+
+        let blocks: Vec<DrCovBasicBlock> = hits
+            .iter()
+            .filter_map(|pc| lens.get(pc).map(|len| (*pc, *len)))
+            .map(|(pc, len)| DrCovBasicBlock::new(pc as u64, pc as u64 + len as u64))
+            .collect();
+
+        for pc in &hits {
+            *self.blocks.entry(*pc).or_default() += 1;
+        }
+
+        let name = file.file_name().unwrap_or(file.as_os_str()).to_string_lossy();
+        DrCovWriter::new(&self.module).write(self.out_dir.join(format!("{name}.drcov")), &blocks)?;
+
+        Ok(blocks.len())
+    }
+
+    /*
+     * Writes <out_dir>/coverage.info over every testcase replayed so far
+     *
+     * The hit count of a line is the number of testcases that executed it,
+     * lines of functions no testcase reached show up with a count of 0
+     * */
+    pub fn write_lcov(&self) -> Result<PathBuf, Error> {
+        let loader = Loader::new(&self.kernel)
+            .map_err(|e| Error::illegal_argument(format!("Failed to read DWARF of {}: {e}", self.kernel.display())))?;
+
+        let line_at = |addr: GuestAddr| -> Option<(String, u32)> {
+            let location = loader.find_location(addr as u64).ok()??;
+            Some((location.file?.to_owned(), location.line?))
+        };
+
+        // Every line the compiler emitted code for
+        let mut lines: BTreeMap<String, BTreeMap<u32, u64>> = BTreeMap::new();
+        for (start, end) in self.symbolizer.functions() {
+            for addr in (start..end).step_by(INSN_ALIGN) {
+                if let Some((file, line)) = line_at(addr) {
+                    lines.entry(file).or_default().entry(line).or_default();
+                }
+            }
+        }
+
+        let lens = BLOCK_LENS.lock().unwrap();
+        for (pc, count) in &self.blocks {
+            let Some(len) = lens.get(pc) else {
+                continue;
+            };
+
+            for addr in (*pc..*pc + *len as GuestAddr).step_by(INSN_ALIGN) {
+                if let Some((file, line)) = line_at(addr) {
+                    let hits = lines.entry(file).or_default().entry(line).or_default();
+                    *hits = (*hits).max(*count);
+                }
+            }
+        }
+
+        let mut info = String::new();
+        for (file, lines) in &lines {
+            let _ = writeln!(info, "TN:");
+            let _ = writeln!(info, "SF:{file}");
+            for (line, hits) in lines {
+                let _ = writeln!(info, "DA:{line},{hits}");
+            }
+            let _ = writeln!(info, "LF:{}", lines.len());
+            let _ = writeln!(info, "LH:{}", lines.values().filter(|hits| **hits > 0).count());
+            let _ = writeln!(info, "end_of_record");
+        }
+
+        let path = self.out_dir.join("coverage.info");
+        fs::write(&path, info)?;
+
+        Ok(path)
+    }
+}
@@ -0,0 +1,338 @@
+use std::{borrow::Cow, fmt};
+use libafl::{
+        corpus::Testcase, executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer}, Error, HasMetadata};
+use libafl_bolts::{impl_serdeany, Named};
+use libafl_qemu::{elf::EasyElf, GuestAddr, GuestReg, Qemu, CPU, Regs};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, RETURN_REG};
+
+/*
+ * System Control Block registers of the Cortex-M
+ *
+ * Only reachable through the CPU, the SCS is not part of the system bus
+ * */
+const VTOR: GuestAddr = 0xE000_ED08;
+const CFSR: GuestAddr = 0xE000_ED28;
+const HFSR: GuestAddr = 0xE000_ED2C;
+const MMFAR: GuestAddr = 0xE000_ED34;
+const BFAR: GuestAddr = 0xE000_ED38;
+
+// CFSR bits, MemManage in 0..8, BusFault in 8..16 and UsageFault in 16..32
+const CFSR_BITS: [(u32, &str); 17] = [
+    (1 << 0, "IACCVIOL: instruction access violation"),
+    (1 << 1, "DACCVIOL: data access violation"),
+    (1 << 3, "MUNSTKERR: MemManage fault on unstacking"),
+    (1 << 4, "MSTKERR: MemManage fault on stacking"),
+    (1 << 5, "MLSPERR: MemManage fault on lazy FP state preservation"),
+    (1 << 8, "IBUSERR: instruction bus error"),
+    (1 << 9, "PRECISERR: precise data bus error"),
+    (1 << 10, "IMPRECISERR: imprecise data bus error"),
+    (1 << 11, "UNSTKERR: BusFault on unstacking"),
+    (1 << 12, "STKERR: BusFault on stacking"),
+    (1 << 13, "LSPERR: BusFault on lazy FP state preservation"),
+    (1 << 16, "UNDEFINSTR: undefined instruction"),
+    (1 << 17, "INVSTATE: invalid EPSR state"),
+    (1 << 18, "INVPC: invalid EXC_RETURN"),
+    (1 << 19, "NOCP: no coprocessor"),
+    (1 << 24, "UNALIGNED: unaligned access"),
+    (1 << 25, "DIVBYZERO: divide by zero"),
+];
+const CFSR_MMARVALID: u32 = 1 << 7;
+const CFSR_BFARVALID: u32 = 1 << 15;
+
+// HFSR bits
+const HFSR_BITS: [(u32, &str); 3] = [
+    (1 << 1, "VECTTBL: bus fault on vector table read"),
+    (1 << 30, "FORCED: escalated from a configurable fault"),
+    (1 << 31, "DEBUGEVT: debug event"),
+];
+
+// Bit of EXC_RETURN telling the exception frame was pushed onto the PSP
+const EXC_RETURN_PSP: u32 = 1 << 2;
+
+// Offset of the stacked PC in the exception frame r0 r1 r2 r3 r12 lr pc xpsr
+const FRAME_PC_OFFSET: GuestAddr = 6 * 4;
+
+// The Cortex-M faults, with their handler symbol and exception number
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    HardFault,
+    MemManage,
+    BusFault,
+    UsageFault,
+}
+
+impl FaultKind {
+    const ALL: [FaultKind; 4] = [
+        FaultKind::HardFault,
+        FaultKind::MemManage,
+        FaultKind::BusFault,
+        FaultKind::UsageFault,
+    ];
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            FaultKind::HardFault => "HardFault_Handler",
+            FaultKind::MemManage => "MemManage_Handler",
+            FaultKind::BusFault => "BusFault_Handler",
+            FaultKind::UsageFault => "UsageFault_Handler",
+        }
+    }
+
+    fn exception(&self) -> GuestAddr {
+        match self {
+            FaultKind::HardFault => 3,
+            FaultKind::MemManage => 4,
+            FaultKind::BusFault => 5,
+            FaultKind::UsageFault => 6,
+        }
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/*
+ * Decoded fault attached to the metadata of a solution
+ *
+ * @var kind   - handler the firmware ended up in
+ * @var pc     - faulting PC from the exception frame, None if it was pushed onto the PSP
+ * @var cfsr   - Configurable Fault Status Register
+ * @var hfsr   - HardFault Status Register
+ * @var mmfar  - MemManage fault address, only when MMARVALID is set
+ * @var bfar   - BusFault address, only when BFARVALID is set
+ * @var causes - every status bit that was set, in words
+ * */
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CortexMFault {
+    pub kind: FaultKind,
+    pub pc: Option<GuestAddr>,
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: Option<GuestAddr>,
+    pub bfar: Option<GuestAddr>,
+    pub causes: Vec<String>,
+}
+
+impl_serdeany!(CortexMFault);
+
+impl CortexMFault {
+    // Reads the fault registers while the CPU sits at the entry of a fault handler
+    pub fn decode(qemu: Qemu, kind: FaultKind) -> Self {
+        let cpu = qemu.cpu_from_index(0);
+
+        let cfsr = read_u32(&cpu, CFSR).unwrap_or(0);
+        let hfsr = read_u32(&cpu, HFSR).unwrap_or(0);
+
+        let mmfar = if cfsr & CFSR_MMARVALID != 0 { read_u32(&cpu, MMFAR).map(GuestAddr::from) } else { None };
+        let bfar = if cfsr & CFSR_BFARVALID != 0 { read_u32(&cpu, BFAR).map(GuestAddr::from) } else { None };
+
+        // Right after exception entry LR holds EXC_RETURN and SP points at the frame, a smashed SP
+        // that runs off the end of the address space has no stacked PC
+        let lr: GuestReg = cpu.read_reg(RETURN_REG.1).unwrap_or(0);
+        let pc = if lr as u32 & EXC_RETURN_PSP == 0 {
+            cpu.read_reg(Regs::Sp)
+                .ok()
+                .and_then(|sp: GuestAddr| sp.checked_add(FRAME_PC_OFFSET))
+                .and_then(|addr| read_u32(&cpu, addr))
+                .map(GuestAddr::from)
+        } else {
+            None
+        };
+
+        let causes = CFSR_BITS
+            .iter()
+            .filter(|(bit, _)| cfsr & bit != 0)
+            .chain(HFSR_BITS.iter().filter(|(bit, _)| hfsr & bit != 0))
+            .map(|(_, cause)| (*cause).to_owned())
+            .collect();
+
+        Self { kind, pc, cfsr, hfsr, mmfar, bfar, causes }
+    }
+}
+
+impl fmt::Display for CortexMFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(pc) = self.pc {
+            write!(f, " at {:#X}", pc)?;
+        }
+        write!(f, " (CFSR {:#010X}, HFSR {:#010X}", self.cfsr, self.hfsr)?;
+        if let Some(mmfar) = self.mmfar {
+            write!(f, ", MMFAR {:#X}", mmfar)?;
+        }
+        if let Some(bfar) = self.bfar {
+            write!(f, ", BFAR {:#X}", bfar)?;
+        }
+        write!(f, ") {}", self.causes.join(", "))
+    }
+}
+
+fn read_u32(cpu: &CPU, addr: GuestAddr) -> Option<u32> {
+    let mut buf = [0u8; 4];
+    cpu.read_mem(addr, &mut buf).ok()?;
+    Some(u32::from_le_bytes(buf))
+}
+
+/*
+ * Where the fault handlers of the firmware live
+ *
+ * Handlers named like the CMSIS ones are taken from the ELF, the rest is read from the
+ * vector table once the firmware is loaded. Boards without one only have the handlers
+ * the ELF names, the example startup code sends every exception to HardFault_Handler
+ *
+ * @var handlers  - fault handlers found so far
+ * @var exit_pad  - reset handler of the firmware, a run can end on it once the boot is over
+ * @var armed     - the exit pad carries its breakpoint, only after the snapshot at main was taken
+ * @var cortex_m  - the board has a vector table to read the rest from
+ * */
+#[derive(Clone, Debug, Default)]
+pub struct FaultHandlers {
+    handlers: Vec<(FaultKind, GuestAddr)>,
+    exit_pad: Option<GuestAddr>,
+    armed: bool,
+    cortex_m: bool,
+}
+
+impl FaultHandlers {
+    pub fn new(elf: &EasyElf, board: &Board) -> Self {
+        let handlers = FaultKind::ALL
+            .iter()
+            .filter_map(|kind| elf.resolve_symbol(kind.symbol(), 0).map(|addr| (*kind, addr & !1)))
+            .collect();
+
+        // Without a vector table the start symbol of the board is the exit pad
+        let exit_pad = if board.cortex_m { None } else { elf.resolve_symbol(board.start_symbol, 0) };
+
+        Self { handlers, exit_pad, armed: false, cortex_m: board.cortex_m }
+    }
+
+    // Fills in the handlers the ELF had no symbol for and the exit pad from the vector table
+    pub fn resolve_vectors(&mut self, qemu: Qemu) {
+        if !self.cortex_m {
+            for (kind, addr) in &self.handlers {
+                info!("{kind} handler: {:#X}", addr);
+            }
+            return;
+        }
+
+        let cpu = qemu.cpu_from_index(0);
+        let vtor: GuestAddr = read_u32(&cpu, VTOR).unwrap_or(0).into();
+
+        for kind in FaultKind::ALL {
+            if self.handlers.iter().any(|(k, _)| *k == kind) {
+                continue;
+            }
+
+            match read_u32(&cpu, vtor + kind.exception() * 4) {
+                Some(addr) if addr != 0 => self.handlers.push((kind, GuestAddr::from(addr) & !1)),
+                _ => {}
+            }
+        }
+
+        for (kind, addr) in &self.handlers {
+            info!("{kind} handler: {:#X}", addr);
+        }
+
+        self.exit_pad = read_u32(&cpu, vtor + 4).filter(|addr| *addr != 0).map(|addr| GuestAddr::from(addr) & !1);
+    }
+
+    pub fn exit_pad(&self) -> GuestAddr {
+        self.exit_pad.expect("No reset handler in the vector table to end faulting runs on")
+    }
+
+    /*
+     * Marks the exit pad as carrying its breakpoint
+     *
+     * The firmware runs through its reset handler while it boots, a breakpoint there before
+     * the snapshot exists would end the first run with nothing to restore. Returns true the
+     * first time, when the breakpoint has to be added
+     * */
+    pub fn arm_exit_pad(&mut self) -> bool {
+        !std::mem::replace(&mut self.armed, true)
+    }
+
+    // Handler addresses without duplicates, firmware often points every fault at one handler
+    pub fn addrs(&self) -> Vec<GuestAddr> {
+        let mut addrs: Vec<GuestAddr> = self.handlers.iter().map(|(_, addr)| *addr).collect();
+        addrs.sort_unstable();
+        addrs.dedup();
+        addrs
+    }
+
+    pub fn kind_at(&self, pc: GuestAddr) -> Option<FaultKind> {
+        self.handlers
+            .iter()
+            .find(|(_, addr)| *addr == pc)
+            .map(|(kind, _)| *kind)
+    }
+}
+
+/*
+ * Moves the fault a strategy left in the state onto the solution
+ *
+ * Never interesting by itself, CrashFeedback decides whether the testcase is a solution
+ *
+ * @var fault - fault of the current execution
+ * */
+#[derive(Debug, Default)]
+pub struct CortexMFaultFeedback {
+    fault: Option<CortexMFault>,
+}
+
+impl CortexMFaultFeedback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Named for CortexMFaultFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("CortexMFaultFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for CortexMFaultFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for CortexMFaultFeedback
+where
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        self.fault = state.metadata_map_mut().remove::<CortexMFault>().map(|fault| *fault);
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        if let Some(fault) = self.fault.take() {
+            info!("{fault}");
+            testcase.add_metadata(fault);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.fault = None;
+        Ok(())
+    }
+}
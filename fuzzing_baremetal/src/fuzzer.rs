@@ -0,0 +1,262 @@
+use std::num::NonZero;
+use libafl::{
+        corpus::{Corpus, InMemoryOnDiskCorpus, OnDiskCorpus},
+        events::{ClientDescription, EventConfig, Launcher}, feedback_and_fast, feedback_not, feedback_or,
+        feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback, TimeoutFeedback}, mutators::StdScheduledMutator,
+        observers::{CanTrack, HitcountsMapObserver, TimeObserver, VariableMapObserver},
+        schedulers::{IndexesLenTimeMinimizerScheduler, QueueScheduler}, stages::{CalibrationStage, StdMutationalStage},
+        state::{HasCorpus, StdState}, Error, Fuzzer, StdFuzzer};
+
+#[cfg(not(feature = "tui"))]
+use libafl::monitors::MultiMonitor;
+#[cfg(feature = "tui")]
+use libafl::monitors::TuiMonitor;
+
+use libafl_bolts::{ownedref::OwnedMutSlice, rands::StdRand,
+                shmem::{ShMemProvider, StdShMemProvider}, tuples::tuple_list};
+use libafl_qemu::{elf::EasyElf, modules::StdEdgeCoverageModule, Emulator, QemuExecutor};
+use libafl_targets::{edges_map_mut_ptr, EDGES_MAP_DEFAULT_SIZE, MAX_EDGES_FOUND};
+use log::info;
+
+use crate::{fault::CortexMFaultFeedback, hang::HangFeedback, icount::InsnCounter, input::{RandWordsGenerator, WordsInput}, logging, mutators::word_mutations,
+            options::FuzzerOptions, report::{CrashReportFeedback, Symbolizer},
+            serial::{capture_path, capture_serial, SerialFeedback, SerialObserver}, stats::ExecRateStage,
+            stack::{stack_bounds, StackDepthFeedback, StackMonitor, StackOverflowFeedback},
+            strategy::{ExecutionStrategy, Strategy}, watch::{WatchFeedback, WatchRegion, Watchpoints}};
+
+pub fn fuzz(options: &FuzzerOptions) {
+    // Parameters from the command line or the config file, see options.rs
+    let timeout = options.timeout;
+    let broker_port = options.broker_port;
+    let cores = &options.cores;
+    let seed_dirs = options.seed_dirs();
+    let crash_dir = &options.crash_dir;
+    let hang_dir = &options.hang_dir;
+
+    info!("Board: {}", options.board);
+    info!("Strategy: {}", options.strategy.label(options.restore));
+    info!("Seed: {}, pass --seed {} to fuzz the same way again", options.seed, options.seed);
+
+    // Creates a growable byte buffer that contains the binary of the elf file
+    let mut elf_buffer = Vec::new();
+    let elf = EasyElf::from_file(options.kernel(), &mut elf_buffer).unwrap();
+
+    // Symbols are resolved once, every client gets its own copy of the strategy
+    let strategy = Strategy::new(options.strategy, &elf, options);
+
+    // Functions of the kernel for the crash reports
+    let symbolizer = Symbolizer::new(&elf);
+
+    // Memory stray writes are looked for in
+    let watch_regions = WatchRegion::resolve_all(&elf, options);
+
+    // Stack SP is followed in, from the linker script symbols
+    let stack = options.stack_check.then(|| stack_bounds(&elf));
+
+    /*
+     * After broker is set up the qemu launcher will invoke to the client process once
+     * Basically each processes main function
+     *
+     * @param state              - if restarting a worker it carries over else fresh start
+     * @param mgr                - event manager built with multimonitor to handle reporting with the broker
+     * @param client_description - meta data that must be linked, its id sets the seed of the client
+     * */
+    let mut run_client = |state: Option<_>, mut mgr, client_description: ClientDescription| {
+        // Everything the client logs from here on ends up in its own file, stderr is lost to the TUI
+        let log_path = logging::log_to_file(&options.log_dir, client_description.id(), !cfg!(feature = "tui"))
+            .unwrap_or_else(|e| panic!("Failed to open log file in {}: {e}", options.log_dir.display()));
+
+        // Every client runs in its own process and captures the serial port into its own file
+        let serial_path = capture_path();
+        let args = capture_serial(&options.qemu_args, &serial_path);
+        let mut strategy = strategy.clone();
+
+        // Clients with the same seed would all mutate the same way
+        let seed = options.seed.wrapping_add(client_description.id() as u64);
+
+        // Created an observeration channel to watch code coverage
+        let mut edges_observer = unsafe {
+            HitcountsMapObserver::new(VariableMapObserver::from_mut_slice(
+                    "edges",
+                    OwnedMutSlice::from_raw_parts_mut(edges_map_mut_ptr(), EDGES_MAP_DEFAULT_SIZE),
+                    &raw mut MAX_EDGES_FOUND,
+            ))
+            .track_indices()
+        };
+
+        // Created an observation channel to keep track of execution time
+        let time_observer = TimeObserver::new("Time");
+
+        // Created an observation channel for what the firmware prints on its serial port
+        let serial_observer = SerialObserver::new(serial_path);
+
+        // emulator_modules here
+        let emulator_mods = tuple_list!(
+            StdEdgeCoverageModule::builder()
+                .map_observer(edges_observer.as_mut())
+                .build()
+                .expect("Failed to intialize coverage map in QEMU")
+        );
+
+        // Initialize QEMU Emulator
+        let mut emu = Emulator::builder()
+            .qemu_parameters(args)
+            .modules(emulator_mods)
+            .build()
+            .expect("Failed to call QEMU emulator");
+
+        // Counts guest instructions and watches memory from the first block the firmware boots with
+        InsnCounter::install(emu.qemu(), options);
+        Watchpoints::install(emu.qemu(), watch_regions.clone());
+        StackMonitor::install(emu.qemu(), stack.clone());
+
+        // Breakpoints, snapshots and whatever else the strategy needs before the first run
+        strategy.prepare(&mut emu);
+
+        // Harness calling the LLVM-style harness
+        let mut harness = |
+            emulator: &mut Emulator<_,_,_,_,_,_,_,>,
+            state: &mut _,
+            input: &WordsInput| strategy.run(emulator, state, input);
+
+        // Feedback to rate the interestingness of an input
+        // Can eitheir be a slower executions or a new coverage, timeouts never go into the queue
+        // and every testcase keeps how deep its stack went
+        let mut feedback = feedback_and_fast!(
+            feedback_not!(TimeoutFeedback::new()),
+            feedback_or!(
+                MaxMapFeedback::new(&edges_observer),
+                TimeFeedback::new(&time_observer),
+                StackDepthFeedback::new(),
+            )
+        );
+
+        // Objective to rate what is a solution
+        // A solution can eitheir be a crash, a stack overflow, a write into a --watch region from code
+        // that may not write it or serial output matching a --serial-objective,
+        // crashes in a fault handler carry the decoded fault, every crash gets a report next to it
+        // and every solution its serial output. Timeouts go to the hang directory instead, one
+        // for every loop the firmware hangs in
+        let mut objective = feedback_or!(
+            CrashFeedback::new(),
+            HangFeedback::new(hang_dir.clone(), symbolizer.clone()),
+            CortexMFaultFeedback::new(),
+            CrashReportFeedback::new(crash_dir.clone(), symbolizer.clone(), seed),
+            StackOverflowFeedback::new(crash_dir.clone(), symbolizer.clone()),
+            WatchFeedback::new(crash_dir.clone(), symbolizer.clone()),
+            SerialFeedback::new(&serial_observer, options.serial_objectives.clone(), crash_dir.clone())
+        );
+
+        // If not restarting state, create a state from scratch
+        let mut state = state.unwrap_or_else(|| {
+            StdState::new(
+                StdRand::with_seed(seed),
+                InMemoryOnDiskCorpus::new(&options.corpus_dir).unwrap(),
+                OnDiskCorpus::new(crash_dir).unwrap(),
+                &mut feedback,
+                &mut objective
+            )
+            .expect("Failed to create state")
+        });
+
+        // Listed with the client in the monitor
+        logging::report_log_file::<_, WordsInput, _>(&mut mgr, &mut state, &log_path)
+            .expect("Failed to report the log file to the broker");
+        info!("Client {} logs to {}", client_description.id(), log_path.display());
+
+        // A minimization + queue policy to grab testcases from the corpus
+        let scheduler =
+            IndexesLenTimeMinimizerScheduler::new(&edges_observer, QueueScheduler::new());
+
+        let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+        // Creating a mutational stage and a calibration stage
+        let mutator = StdScheduledMutator::new(word_mutations());
+        let calibration_feedback = MaxMapFeedback::new(&edges_observer);
+
+        let mut stages = tuple_list!(
+            StdMutationalStage::new(mutator),
+            CalibrationStage::new(&calibration_feedback),
+            ExecRateStage::new(&options.strategy.label(options.restore))
+        );
+
+        // Intializing the QEMU in-process executor
+        let mut executor = QemuExecutor::new(
+            emu,
+            &mut harness,
+            tuple_list!(edges_observer, time_observer, serial_observer),
+            &mut fuzzer,
+            &mut state,
+            &mut mgr,
+            timeout
+        )
+        .expect("Failed to start QEMU executor");
+
+        // trigger a breakpoint
+        executor.break_on_timeout();
+
+        if state.must_load_initial_inputs() {
+            // Seeds plus whatever a previous campaign left in the corpus directory
+            if !seed_dirs.is_empty() {
+                state
+                    .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &seed_dirs)
+                    .expect("Failed to load testcases from the seed directories");
+                info!("Loaded {} testcases from {:?}", state.corpus().count(), seed_dirs);
+            }
+
+            // Only fall back to random inputs when there was nothing to load
+            if state.corpus().count() == 0 {
+                // The interrupt schedule comes in front of the words written into the firmware
+                let mut generator = RandWordsGenerator::new(
+                    NonZero::new(options.irqs + options.max_input_size).expect("--max-input-size has to be at least 1")
+                );
+                let test_cases = 8;
+                state.generate_initial_inputs_forced(&mut fuzzer, &mut executor, &mut generator, &mut mgr, test_cases)
+                        .expect("Failed to load empty corpus with intial input");
+
+                info!("Generated {test_cases} testcases into corpus");
+            }
+        }
+
+        info!("Corpus Size: {}", state.corpus().count());
+        fuzzer
+            .fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+
+        Ok(())
+    };
+
+    // Shared Memory allocator so processes can communicate with eachother
+    let shmem_provider = StdShMemProvider::new().expect("Failed to init shared memory");
+
+    // Stats reporter for the broker
+    #[cfg(not(feature = "tui"))]
+    let monitor = MultiMonitor::new(|msg| info!("{msg}"));
+
+    #[cfg(feature = "tui")]
+    let monitor = TuiMonitor::builder()
+        .enhanced_graphics(true)
+        .title(format!("Fuzzing {} firmware with {}, logs in {}", options.board, options.strategy, options.log_dir.display()))
+        .build();
+
+    // if tui is enabled fuzzer output would cover it, the clients log into --log-dir instead
+    let output = if cfg!(feature = "tui") { Some("/dev/null") } else { None };
+
+    // Build and run launcher
+    match Launcher::builder()
+        .shmem_provider(shmem_provider)
+        .broker_port(broker_port)
+        .configuration(EventConfig::from_build_id())
+        .monitor(monitor)
+        .run_client(&mut run_client)
+        .cores(cores)
+        .stdout_file(output)
+        .stderr_file(output)
+        .build()
+        .launch()
+        {
+            Ok(()) => (),
+            Err(Error::ShuttingDown) => info!("User stopped fuzzing process"),
+            Err(e) => panic!("Failed to run launcher: {e:?}"),
+        }
+}
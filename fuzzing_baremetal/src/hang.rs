@@ -0,0 +1,150 @@
+use std::{borrow::Cow, collections::HashSet, fmt::Write as _, fs, marker::PhantomData, path::PathBuf};
+use libafl::{
+        corpus::{Corpus, OnDiskCorpus, Testcase}, events::{Event, EventFirer}, executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer}, inputs::Input,
+        monitors::stats::{AggregatorOps, UserStats, UserStatsValue}, Error, HasMetadata};
+use libafl_bolts::{hash_std, impl_serdeany, Named};
+use libafl_qemu::GuestAddr;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{icount::InsnCounter, report::Symbolizer};
+
+/*
+ * Where a timed out run was stuck, sampled from the blocks it executed last
+ *
+ * A firmware that hangs goes around the same loop until the timeout, so the blocks are
+ * the same whatever input led it there and wherever in the loop it got stopped
+ *
+ * @var blocks - start addresses of the blocks, sorted and without duplicates
+ * */
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HangLoop {
+    pub blocks: Vec<GuestAddr>,
+}
+
+impl_serdeany!(HangLoop);
+
+impl HangLoop {
+    pub fn sample() -> Self {
+        let mut blocks = InsnCounter::recent_blocks();
+        blocks.sort_unstable();
+        blocks.dedup();
+        Self { blocks }
+    }
+
+    // Same loop, same signature, in every client
+    pub fn signature(&self) -> u64 {
+        let bytes: Vec<u8> = self.blocks.iter().flat_map(|pc| pc.to_le_bytes()).collect();
+        hash_std(&bytes)
+    }
+
+    pub fn to_text(&self, symbolizer: &Symbolizer) -> String {
+        let mut text = String::new();
+
+        let _ = writeln!(text, "Hang in a loop of {} blocks:", self.blocks.len());
+        for pc in &self.blocks {
+            let symbol = symbolizer.symbolize(*pc).unwrap_or_else(|| "??".to_owned());
+            let _ = writeln!(text, "  {pc:#010x} {symbol}");
+        }
+
+        text
+    }
+}
+
+/*
+ * Second objective next to the crashes, keeps one input for every loop the firmware was
+ * found hanging in
+ *
+ * Hangs never reach the solutions of the state, they go into a corpus of their own in the
+ * hang directory as hang-<signature> with the HangLoop as testcase metadata and the loop
+ * written out next to them as hang-<signature>.hang.txt. The number of hangs a client found
+ * is sent to the monitor as a user stat so it shows up apart from the crashes
+ *
+ * @var hang_dir   - where the hangs and their loops go
+ * @var hangs      - corpus of the hangs, on disk in hang_dir
+ * @var symbolizer - functions of the kernel
+ * @var seen       - signatures of the loops this client already looked at
+ * */
+#[derive(Debug)]
+pub struct HangFeedback<I> {
+    hang_dir: PathBuf,
+    hangs: OnDiskCorpus<I>,
+    symbolizer: Symbolizer,
+    seen: HashSet<u64>,
+}
+
+impl<I> HangFeedback<I> {
+    pub fn new(hang_dir: PathBuf, symbolizer: Symbolizer) -> Self {
+        let hangs = OnDiskCorpus::new(&hang_dir)
+            .unwrap_or_else(|e| panic!("Failed to create hang directory {}: {e}", hang_dir.display()));
+
+        Self {
+            hang_dir,
+            hangs,
+            symbolizer,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl<I> Named for HangFeedback<I> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("HangFeedback");
+        &NAME
+    }
+}
+
+impl<I, S> StateInitializer<S> for HangFeedback<I> {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for HangFeedback<I>
+where
+    EM: EventFirer<I, S>,
+    I: Input + Clone,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let hang = state.metadata_map_mut().remove::<HangLoop>();
+        let (ExitKind::Timeout, Some(hang)) = (exit_kind, hang) else {
+            return Ok(false);
+        };
+
+        let signature = hang.signature();
+        if !self.seen.insert(signature) {
+            return Ok(false);
+        }
+
+        // Another client may have found the same loop first
+        let name = format!("hang-{signature:016x}");
+        let path = self.hang_dir.join(&name);
+        if path.exists() {
+            return Ok(false);
+        }
+
+        fs::write(self.hang_dir.join(format!("{name}.hang.txt")), hang.to_text(&self.symbolizer))?;
+        info!("New hang {name} in a loop of {} blocks", hang.blocks.len());
+
+        let mut testcase = Testcase::new(input.clone());
+        *testcase.filename_mut() = Some(name);
+        testcase.add_metadata(hang);
+        self.hangs.add(testcase)?;
+
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: Cow::Borrowed("hangs"),
+                value: UserStats::new(UserStatsValue::Number(self.hangs.count() as u64), AggregatorOps::Sum),
+                phantom: PhantomData,
+            },
+        )?;
+
+        Ok(false)
+    }
+}
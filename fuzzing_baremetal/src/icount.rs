@@ -0,0 +1,154 @@
+use std::{collections::{BTreeMap, VecDeque}, sync::Mutex};
+use libafl_qemu::{sys::libafl_exit_request_timeout, GuestAddr, GuestUsize, Qemu};
+use log::info;
+
+use crate::{board::count_insns, options::FuzzerOptions};
+
+// Blocks of a run that are remembered, a loop the firmware hangs in has to fit in here
+const RECENT_BLOCKS: usize = 256;
+
+/*
+ * Guest instructions executed by the current run and what the hooks know about the firmware
+ *
+ * @var installed   - set once the block hooks are in place
+ * @var limit       - instructions a run may execute before it is a timeout, 0 for no limit
+ * @var block_insns - instructions of every translated block, by its start address
+ * @var executed    - instructions executed since the run started
+ * @var recent      - start addresses of the last RECENT_BLOCKS blocks of the run, the latest last
+ * @var active      - set while a testcase runs, boot code is only counted
+ * @var expired     - the run went past limit and QEMU was asked to stop
+ * @var listener    - called after every block of a run with the instructions executed so far
+ * */
+struct Counter {
+    installed: bool,
+    limit: u64,
+    block_insns: BTreeMap<GuestAddr, u64>,
+    executed: u64,
+    recent: VecDeque<GuestAddr>,
+    active: bool,
+    expired: bool,
+    listener: Option<fn(Qemu, u64)>,
+}
+
+static COUNTER: Mutex<Counter> = Mutex::new(Counter {
+    installed: false,
+    limit: 0,
+    block_insns: BTreeMap::new(),
+    executed: 0,
+    recent: VecDeque::new(),
+    active: false,
+    expired: false,
+    listener: None,
+});
+
+// The id of a block is its start address, handed to the exec hook every time it runs
+unsafe extern "C" fn gen_block(_data: u64, pc: GuestAddr) -> u64 {
+    pc as u64
+}
+
+// Counts the instructions of a block once it is translated
+unsafe extern "C" fn post_gen_block(_data: u64, pc: GuestAddr, len: GuestUsize) {
+    let Some(qemu) = Qemu::get() else {
+        return;
+    };
+
+    let mut code = vec![0u8; len as usize];
+    if qemu.cpu_from_index(0).read_mem(pc, &mut code).is_err() {
+        return;
+    }
+
+    COUNTER.lock().unwrap().block_insns.insert(pc, count_insns(&code));
+}
+
+unsafe extern "C" fn exec_block(_data: u64, id: u64) {
+    let Some(qemu) = Qemu::get() else {
+        return;
+    };
+
+    let mut counter = COUNTER.lock().unwrap();
+    if !counter.active {
+        return;
+    }
+
+    counter.executed += counter.block_insns.get(&(id as GuestAddr)).copied().unwrap_or(0);
+    if counter.recent.len() == RECENT_BLOCKS {
+        counter.recent.pop_front();
+    }
+    counter.recent.push_back(id as GuestAddr);
+
+    // Ends the run the same way the wall clock timeout of the executor does
+    if counter.limit > 0 && counter.executed > counter.limit && !counter.expired {
+        counter.expired = true;
+        unsafe { libafl_exit_request_timeout() };
+    }
+
+    // The listener looks at the counter itself
+    let (executed, listener) = (counter.executed, counter.listener);
+    drop(counter);
+    if let Some(listener) = listener {
+        listener(qemu, executed);
+    }
+}
+
+/*
+ * Counts the guest instructions of every run
+ *
+ * A run that goes past --timeout-insns ends as a timeout at the same block boundary every
+ * time, however fast the host is. The interrupt schedule counts with it as well and a
+ * timed out run is told apart by the blocks it executed last
+ *
+ * The hooks live for the whole process, every run starts counting from zero
+ * */
+pub struct InsnCounter;
+
+impl InsnCounter {
+    // Has to happen before the firmware boots, blocks translated earlier are never counted
+    pub fn install(qemu: Qemu, options: &FuzzerOptions) {
+        let mut counter = COUNTER.lock().unwrap();
+        if counter.installed {
+            return;
+        }
+        counter.installed = true;
+        counter.limit = options.timeout_insns;
+
+        if counter.limit > 0 {
+            info!("Runs time out after {} guest instructions", counter.limit);
+        }
+
+        qemu.hooks().add_block_hooks(0u64, Some(gen_block), Some(post_gen_block), Some(exec_block));
+    }
+
+    // Called after every block a run executes, e.g. to pend interrupts that are due
+    pub fn set_listener(listener: fn(Qemu, u64)) {
+        COUNTER.lock().unwrap().listener = Some(listener);
+    }
+
+    pub fn start() {
+        let mut counter = COUNTER.lock().unwrap();
+        counter.executed = 0;
+        counter.recent.clear();
+        counter.expired = false;
+        counter.active = counter.installed;
+    }
+
+    // Ends the run, returns the instructions it executed
+    pub fn stop() -> u64 {
+        let mut counter = COUNTER.lock().unwrap();
+        counter.active = false;
+        counter.executed
+    }
+
+    pub fn executed() -> u64 {
+        COUNTER.lock().unwrap().executed
+    }
+
+    // Start addresses of the last blocks the run executed, oldest first
+    pub fn recent_blocks() -> Vec<GuestAddr> {
+        COUNTER.lock().unwrap().recent.iter().copied().collect()
+    }
+
+    // Start addresses of the blocks translated so far
+    pub fn blocks() -> Vec<GuestAddr> {
+        COUNTER.lock().unwrap().block_insns.keys().copied().collect()
+    }
+}
@@ -0,0 +1,122 @@
+use std::{fs, hash::Hash, num::NonZero, path::Path};
+use libafl::{
+        generators::Generator, inputs::{HasTargetBytes, Input},
+        state::HasRand, Error};
+use libafl_bolts::{ownedref::OwnedSlice, rands::Rand, HasLen};
+use serde::{Deserialize, Serialize};
+
+// Size of one element of the input buffer of the firmware
+pub const WORD_SIZE: usize = size_of::<u32>();
+
+// Values the example firmware compares against, plus the extremes
+pub const INTERESTING_WORDS: [u32; 4] = [0, 100, 101, u32::MAX];
+
+/*
+ * Testcase for firmware that takes an array of u32
+ *
+ * Written into the input buffer as little-endian words, on disk it is the same raw
+ * bytes so testcases can be edited with a hex editor and replayed as is
+ *
+ * @var words - elements of the array, never more than --max-input-size of them reach the firmware
+ * */
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct WordsInput {
+    words: Vec<u32>,
+}
+
+impl WordsInput {
+    pub fn new(words: Vec<u32>) -> Self {
+        Self { words }
+    }
+
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    pub fn words_mut(&mut self) -> &mut Vec<u32> {
+        &mut self.words
+    }
+
+    // Trailing bytes that do not fill a whole word are dropped
+    pub fn from_le_bytes(bytes: &[u8]) -> Self {
+        let words = bytes
+            .chunks_exact(WORD_SIZE)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        Self { words }
+    }
+
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+}
+
+impl Input for WordsInput {
+    fn to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        fs::write(path, self.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::from_le_bytes(&fs::read(path)?))
+    }
+}
+
+impl HasTargetBytes for WordsInput {
+    fn target_bytes(&self) -> OwnedSlice<'_, u8> {
+        OwnedSlice::from(self.to_le_bytes())
+    }
+}
+
+impl HasLen for WordsInput {
+    fn len(&self) -> usize {
+        self.words.len()
+    }
+}
+
+/*
+ * Generates full word arrays for an empty corpus
+ *
+ * Every array has max_words elements so the firmware never reads words left over
+ * from a previous run, a quarter of the words are interesting values
+ *
+ * @var max_words - elements per generated testcase
+ * */
+#[derive(Clone, Debug)]
+pub struct RandWordsGenerator {
+    max_words: NonZero<usize>,
+}
+
+impl RandWordsGenerator {
+    pub fn new(max_words: NonZero<usize>) -> Self {
+        Self { max_words }
+    }
+}
+
+impl<S> Generator<WordsInput, S> for RandWordsGenerator
+where
+    S: HasRand,
+{
+    fn generate(&mut self, state: &mut S) -> Result<WordsInput, Error> {
+        let rand = state.rand_mut();
+
+        let words = (0..self.max_words.get())
+            .map(|_| {
+                if rand.coinflip(0.25) {
+                    *rand.choose(&INTERESTING_WORDS).unwrap()
+                } else {
+                    rand.next() as u32
+                }
+            })
+            .collect();
+
+        Ok(WordsInput::new(words))
+    }
+}
@@ -0,0 +1,244 @@
+use std::{collections::BTreeSet, sync::Mutex};
+use libafl_qemu::{elf::EasyElf, GuestAddr, GuestReg, Qemu, Regs};
+use log::info;
+
+use crate::{board::ARG_REGS, icount::InsnCounter, input::WordsInput};
+
+// Program header type of a loadable segment
+const PT_LOAD: u32 = 1;
+
+// Interrupt Set-Pending Register of the NVIC, one bit per external interrupt
+const NVIC_ISPR: GuestAddr = 0xE000_E200;
+
+// External interrupts of the mps2-an385, all of them fit into the first ISPR
+pub const IRQ_LINES: u32 = 32;
+
+/*
+ * Thumb code the CPU is sent through to pend an interrupt
+ *
+ * QEMU drops debug writes to the NVIC, the store has to come from the CPU itself
+ *
+ *   str r1, [r0]  @ r0 = NVIC_ISPR, r1 = bit of the interrupt
+ *   dsb
+ *   isb           @ an enabled and unmasked interrupt is taken right here
+ *   b .           @ carries a breakpoint, the firmware goes back to where it was stopped
+ * */
+const PEND_STUB: [u16; 6] = [0x6001, 0xF3BF, 0x8F4F, 0xF3BF, 0x8F6F, 0xE7FE];
+
+// Offset of the b . in PEND_STUB
+const STUB_RETURN: GuestAddr = 10;
+
+/*
+ * One interrupt of the schedule at the start of a testcase
+ *
+ * The low byte of a schedule word is the IRQ number, wrapping around IRQ_LINES, the
+ * upper 24 bits are the instructions executed since main before it gets pended
+ *
+ * @var at  - instruction count the interrupt is due at
+ * @var irq - external interrupt, 0 is the first entry after SysTick in the vector table
+ * */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ScheduledIrq {
+    at: u64,
+    irq: u32,
+}
+
+impl ScheduledIrq {
+    fn from_word(word: u32) -> Self {
+        Self { at: (word >> 8) as u64, irq: (word & 0xFF) % IRQ_LINES }
+    }
+}
+
+/*
+ * Schedule of the current run and what the hooks know about the firmware
+ *
+ * @var stub     - where PEND_STUB was written, None until the injector is installed
+ * @var schedule - interrupts still to come, the next one last
+ * @var armed    - block starts carrying a breakpoint because the next interrupt is due
+ * @var saved    - r0, r1 and pc of the firmware while it is inside the stub
+ * @var active   - set while a testcase runs
+ * */
+struct Injector {
+    stub: Option<GuestAddr>,
+    schedule: Vec<ScheduledIrq>,
+    armed: BTreeSet<GuestAddr>,
+    saved: Option<[GuestReg; 3]>,
+    active: bool,
+}
+
+static INJECTOR: Mutex<Injector> = Mutex::new(Injector {
+    stub: None,
+    schedule: Vec::new(),
+    armed: BTreeSet::new(),
+    saved: None,
+    active: false,
+});
+
+impl Injector {
+    /*
+     * Stops the firmware at the next block it runs once the next interrupt is due
+     *
+     * Blocks are the finest grain the hooks see, so every block start known so far gets
+     * a breakpoint until the firmware stops at one of them
+     * */
+    fn arm_if_due(&mut self, qemu: Qemu, executed: u64) {
+        let Some(stub) = self.stub else {
+            return;
+        };
+        let due = self.schedule.last().is_some_and(|next| next.at <= executed);
+        if !due || !self.armed.is_empty() || self.saved.is_some() {
+            return;
+        }
+
+        let stub = stub..stub + (PEND_STUB.len() * size_of::<u16>()) as GuestAddr;
+        self.armed = InsnCounter::blocks().into_iter().filter(|pc| !stub.contains(pc)).collect();
+        for pc in &self.armed {
+            qemu.set_breakpoint(*pc);
+        }
+    }
+
+    fn disarm(&mut self, qemu: Qemu) {
+        for pc in std::mem::take(&mut self.armed) {
+            qemu.remove_breakpoint(pc);
+        }
+    }
+}
+
+// Listener of the instruction counter, runs after every block of a run
+fn on_block(qemu: Qemu, executed: u64) {
+    let mut injector = INJECTOR.lock().unwrap();
+    if injector.active {
+        injector.arm_if_due(qemu, executed);
+    }
+}
+
+/*
+ * Where PEND_STUB goes, right behind everything the kernel loads
+ *
+ * @param elf - firmware image, the board has to have memory after its last segment
+ * */
+pub fn stub_addr(elf: &EasyElf) -> GuestAddr {
+    let end = elf
+        .goblin()
+        .program_headers
+        .iter()
+        .filter(|ph| ph.p_type == PT_LOAD)
+        .map(|ph| ph.p_vaddr + ph.p_memsz)
+        .max()
+        .expect("No loadable segment in the kernel");
+
+    end.next_multiple_of(4) as GuestAddr
+}
+
+// Splits a testcase into its interrupt schedule and the bytes the firmware gets
+pub fn split_input(input: &WordsInput, irqs: usize) -> (&[u32], Vec<u8>) {
+    let (schedule, payload) = input.words().split_at(irqs.min(input.words().len()));
+    (schedule, payload.iter().flat_map(|word| word.to_le_bytes()).collect())
+}
+
+/*
+ * Pends NVIC interrupts at the instruction counts the testcase asks for
+ *
+ * Instructions are counted by the InsnCounter, so the same testcase interrupts the firmware
+ * at the same block boundary every time whatever -icount shift QEMU runs with. A due
+ * interrupt stops the firmware at the next block, the CPU runs PEND_STUB and whatever
+ * handler that let in, then picks up where it was stopped
+ *
+ * The hooks live for the whole process, every run hands them a new schedule
+ * */
+pub struct IrqInjector;
+
+impl IrqInjector {
+    // The InsnCounter has to be installed as well, before the firmware boots
+    pub fn install(qemu: Qemu, stub: GuestAddr) {
+        let code: Vec<u8> = PEND_STUB.iter().flat_map(|hw| hw.to_le_bytes()).collect();
+        qemu.cpu_from_index(0)
+            .write_mem(stub, &code)
+            .expect("Failed to write the interrupt stub");
+        qemu.set_breakpoint(stub + STUB_RETURN);
+
+        INJECTOR.lock().unwrap().stub = Some(stub);
+        info!("Interrupts are pended through the stub at {stub:#X}");
+
+        InsnCounter::set_listener(on_block);
+    }
+
+    // Starts counting for a testcase, schedule holds its leading words
+    pub fn start(qemu: Qemu, schedule: &[u32]) {
+        let mut injector = INJECTOR.lock().unwrap();
+        if injector.stub.is_none() {
+            return;
+        }
+
+        injector.disarm(qemu);
+        injector.schedule = schedule.iter().map(|word| ScheduledIrq::from_word(*word)).collect();
+        // Taken from the back, the earliest interrupt goes last
+        injector.schedule.sort_by(|a, b| b.cmp(a));
+        injector.saved = None;
+        injector.active = true;
+
+        injector.arm_if_due(qemu, InsnCounter::executed());
+    }
+
+    /*
+     * Looks at the breakpoint the firmware stopped at
+     *
+     * Returns true if it belonged to the injector, the firmware is then either on its way
+     * into PEND_STUB or back where it was interrupted and the run goes on
+     * */
+    pub fn handle_stop(qemu: Qemu) -> bool {
+        let cpu = qemu.cpu_from_index(0);
+        let pc: GuestAddr = cpu.read_reg(Regs::Pc).unwrap_or(0);
+
+        let mut injector = INJECTOR.lock().unwrap();
+        if !injector.active {
+            return false;
+        }
+        let Some(stub) = injector.stub else {
+            return false;
+        };
+
+        // Back from the stub and whatever handler it let in
+        if pc == stub + STUB_RETURN {
+            let Some([r0, r1, pc]) = injector.saved.take() else {
+                return false;
+            };
+            cpu.write_reg(ARG_REGS[0], r0).expect("Failed to restore R0");
+            cpu.write_reg(ARG_REGS[1], r1).expect("Failed to restore R1");
+            cpu.write_reg(Regs::Pc, pc).expect("Failed to return from the interrupt stub");
+
+            // Several interrupts can be due at the same count
+            injector.arm_if_due(qemu, InsnCounter::executed());
+            return true;
+        }
+
+        if !injector.armed.contains(&pc) {
+            return false;
+        }
+        injector.disarm(qemu);
+
+        let Some(next) = injector.schedule.pop() else {
+            return false;
+        };
+
+        let r0: GuestReg = cpu.read_reg(ARG_REGS[0]).expect("Failed to read R0");
+        let r1: GuestReg = cpu.read_reg(ARG_REGS[1]).expect("Failed to read R1");
+        injector.saved = Some([r0, r1, pc as GuestReg]);
+
+        cpu.write_reg(ARG_REGS[0], NVIC_ISPR).expect("Failed to write R0");
+        cpu.write_reg(ARG_REGS[1], 1u32 << next.irq).expect("Failed to write R1");
+        cpu.write_reg(Regs::Pc, stub).expect("Failed to enter the interrupt stub");
+
+        true
+    }
+
+    // Ends the run, interrupts the firmware did not get to are dropped, one pended but not
+    // taken yet is only undone by the snapshot restore so --irqs needs one
+    pub fn stop(qemu: Qemu) {
+        let mut injector = INJECTOR.lock().unwrap();
+        injector.disarm(qemu);
+        injector.schedule.clear();
+        injector.saved = None;
+        injector.active = false;
+    }
+}
@@ -0,0 +1,146 @@
+use std::{borrow::Cow, fs::{self, File, OpenOptions}, io::{self, Write}, marker::PhantomData, path::{Path, PathBuf}, sync::Mutex};
+use env_logger::{Env, Target};
+use libafl::{events::{Event, EventFirer}, monitors::stats::{AggregatorOps, UserStats, UserStatsValue}, Error};
+
+// A client log is rotated once it grows past this
+const MAX_LOG_BYTES: u64 = 8 * 1024 * 1024;
+
+// Rotated logs kept next to the current one, <log>.1 is the newest of them
+const KEPT_LOGS: usize = 3;
+
+/*
+ * Log file of a Launcher client
+ *
+ * @var file    - open in append mode so a restarted client carries on where it stopped
+ * @var path    - where it lives, rotated logs get .1, .2, ... appended
+ * @var written - bytes in the file so far
+ * */
+struct LogFile {
+    file: File,
+    path: PathBuf,
+    written: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self { file, path: path.to_owned(), written })
+    }
+
+    // <log>.2 becomes <log>.3 and so on, the oldest one is dropped
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{n}", self.path.display()));
+
+        for n in (1..KEPT_LOGS).rev() {
+            if rotated(n).exists() {
+                fs::rename(rotated(n), rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(1))?;
+
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        if self.written > 0 && self.written + record.len() as u64 > MAX_LOG_BYTES {
+            self.rotate()?;
+        }
+        self.file.write_all(record)?;
+        self.written += record.len() as u64;
+        Ok(())
+    }
+}
+
+/*
+ * Where the log records of this process go
+ *
+ * Every Launcher client is a fork of the process that set the logger up, so each of them
+ * points its own copy of this at its own file
+ *
+ * @var file   - log file of the client, None outside of a client
+ * @var stderr - records are written to stderr as well
+ * */
+struct Sink {
+    file: Option<LogFile>,
+    stderr: bool,
+}
+
+static SINK: Mutex<Sink> = Mutex::new(Sink { file: None, stderr: true });
+
+// Handed to env_logger, every record arrives in a single write
+struct SinkWriter;
+
+impl Write for SinkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut sink = SINK.lock().unwrap();
+        if sink.stderr {
+            io::stderr().write_all(buf)?;
+        }
+        if let Some(file) = &mut sink.file {
+            file.write_record(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut sink = SINK.lock().unwrap();
+        if let Some(file) = &mut sink.file {
+            file.file.flush()?;
+        }
+        io::stderr().flush()
+    }
+}
+
+/*
+ * Sets up log and env_logger for the whole process
+ *
+ * Records go to stderr until a client opens its log file, RUST_LOG picks the level,
+ * info without it
+ * */
+pub fn init() {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+        .format_timestamp_millis()
+        .target(Target::Pipe(Box::new(SinkWriter)))
+        .init();
+}
+
+/*
+ * Sends the records of this client into its own log file from now on
+ *
+ * @param log_dir - directory the client logs go to
+ * @param id      - id of the client, names the file
+ * @param stderr  - keep writing the records to stderr as well
+ * */
+pub fn log_to_file(log_dir: &Path, id: usize, stderr: bool) -> io::Result<PathBuf> {
+    fs::create_dir_all(log_dir)?;
+    let path = log_dir.join(format!("client-{id}.log"));
+
+    let mut sink = SINK.lock().unwrap();
+    sink.file = Some(LogFile::open(&path)?);
+    sink.stderr = stderr;
+
+    Ok(path)
+}
+
+/*
+ * Tells the monitor where the log of this client is, the TUI lists it with the client
+ *
+ * @param mgr   - event manager of the client
+ * @param state - state of the client
+ * @param path  - log file from log_to_file
+ * */
+pub fn report_log_file<EM, I, S>(mgr: &mut EM, state: &mut S, path: &Path) -> Result<(), Error>
+where
+    EM: EventFirer<I, S>,
+{
+    mgr.fire(
+        state,
+        Event::UpdateUserStats {
+            name: Cow::Borrowed("log"),
+            value: UserStats::new(UserStatsValue::String(Cow::Owned(path.display().to_string())), AggregatorOps::None),
+            phantom: PhantomData,
+        },
+    )
+}
@@ -1,22 +1,75 @@
-#[cfg(all(target_os = "linux", feature = "breakpoint"))]
-mod fuzzer_breakpoint;
+#[cfg(target_os = "linux")]
+mod board;
+
+#[cfg(target_os = "linux")]
+mod coverage;
+
+#[cfg(target_os = "linux")]
+mod fault;
+
+#[cfg(target_os = "linux")]
+mod fuzzer;
+
+#[cfg(target_os = "linux")]
+mod hang;
+
+#[cfg(target_os = "linux")]
+mod icount;
+
+#[cfg(target_os = "linux")]
+mod input;
+
+#[cfg(target_os = "linux")]
+mod irq;
 
-#[cfg(all(target_os = "linux", feature = "sync_exit"))]
-mod fuzzer_sync_exit; 
+#[cfg(target_os = "linux")]
+mod logging;
 
-#[cfg(all(target_os = "linux", feature = "low_level"))]
-mod fuzzer_low_level;
+#[cfg(target_os = "linux")]
+mod mmio;
+
+#[cfg(target_os = "linux")]
+mod mutators;
+
+#[cfg(target_os = "linux")]
+mod options;
+
+#[cfg(target_os = "linux")]
+mod replay;
+
+#[cfg(target_os = "linux")]
+mod report;
+
+#[cfg(target_os = "linux")]
+mod semihosting;
+
+#[cfg(target_os = "linux")]
+mod serial;
+
+#[cfg(target_os = "linux")]
+mod stack;
+
+#[cfg(target_os = "linux")]
+mod stats;
+
+#[cfg(target_os = "linux")]
+mod strategy;
+
+#[cfg(target_os = "linux")]
+mod watch;
 
 #[cfg(target_os = "linux")]
 pub fn main() {
-    #[cfg(feature = "low_level")]
-    fuzzer_low_level::fuzz();
-    
-    #[cfg(feature = "breakpoint")]
-    fuzzer_breakpoint::fuzz();
-   
-    #[cfg(feature = "sync_exit")]
-    fuzzer_sync_exit::fuzz();
+    // Records go to stderr, every Launcher client moves them into its own file
+    logging::init();
+
+    // Fuzzer options come first, QEMU arguments follow after `--`
+    let options = options::FuzzerOptions::parse();
+
+    match &options.replay {
+        Some(replay) => replay::replay(&options, replay),
+        None => fuzzer::fuzz(&options),
+    }
 }
 
 #[cfg(not(target_os = "linux"))]
@@ -0,0 +1,346 @@
+use std::{collections::BTreeMap, fmt, ops::Range, str::FromStr, sync::Mutex};
+use libafl_qemu::{sys::TCGTemp, GuestAddr, MemAccessInfo, Qemu, Regs};
+use log::info;
+use serde::Deserialize;
+
+/*
+ * Peripheral registers of the mps2-an385 fed from the input when no --mmio is given
+ *
+ * APB peripherals (timers, UARTs, watchdog) and the AHB GPIO
+ * */
+pub const DEFAULT_MMIO_RANGES: [MmioRange; 2] = [
+    MmioRange { start: 0x4000_0000, end: 0x4001_0000 },
+    MmioRange { start: 0x4001_0000, end: 0x4001_2000 },
+];
+
+// Returned from a gen hook to leave the access uninstrumented
+const NO_HOOK: u64 = u64::MAX;
+
+// Registers a Thumb load can write to, indexed by Rt
+#[cfg(feature = "arm")]
+const LOAD_REGS: [Regs; 15] = [
+    Regs::R0, Regs::R1, Regs::R2, Regs::R3, Regs::R4, Regs::R5, Regs::R6, Regs::R7,
+    Regs::R8, Regs::R9, Regs::R10, Regs::R11, Regs::R12, Regs::Sp, Regs::Lr,
+];
+
+// Only Cortex-M boards get the hooks, other architectures decode no loads
+#[cfg(not(feature = "arm"))]
+const LOAD_REGS: [Regs; 0] = [];
+
+/*
+ * Address range of peripheral registers whose reads are served from the input
+ *
+ * Written as START-END or START+SIZE, hex with 0x or decimal e.g. 0x40004000+0x1000
+ * */
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct MmioRange {
+    pub start: GuestAddr,
+    pub end: GuestAddr,
+}
+
+impl MmioRange {
+    fn contains(&self, addr: GuestAddr) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+}
+
+fn parse_addr(s: &str) -> Result<GuestAddr, String> {
+    let s = s.trim();
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => GuestAddr::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| format!("Invalid address '{s}': {e}"))
+}
+
+impl FromStr for MmioRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = if let Some((start, end)) = s.split_once('-') {
+            (parse_addr(start)?, parse_addr(end)?)
+        } else if let Some((start, size)) = s.split_once('+') {
+            let start = parse_addr(start)?;
+            let end = start
+                .checked_add(parse_addr(size)?)
+                .ok_or_else(|| format!("MMIO range '{s}' wraps around"))?;
+            (start, end)
+        } else {
+            return Err(format!("MMIO range '{s}' is neither START-END nor START+SIZE"));
+        };
+
+        if start >= end {
+            return Err(format!("MMIO range '{s}' is empty"));
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+impl TryFrom<String> for MmioRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for MmioRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#X}-{:#X}", self.start, self.end)
+    }
+}
+
+/*
+ * A Thumb load that can read a peripheral register
+ *
+ * @var next   - address of the following instruction, where the loaded register gets overwritten
+ * @var reg    - register the load writes
+ * @var signed - LDRSB / LDRSH sign extend the value
+ * */
+#[derive(Clone, Copy, Debug)]
+struct LoadSite {
+    next: GuestAddr,
+    reg: Regs,
+    signed: bool,
+}
+
+/*
+ * Decodes the single register loads of Thumb and Thumb-2
+ *
+ * LDRD, LDM and POP load more than one register and are left alone, reads through them
+ * still reach the peripheral
+ * */
+fn decode_load(pc: GuestAddr, hw1: u16, hw2: u16) -> Option<LoadSite> {
+    // Thumb-2 instructions start with 0b11101, 0b11110 or 0b11111
+    if hw1 >> 11 >= 0b11101 {
+        // LDR{B,H,SB,SH}.W, immediate and register offset: 1111 100S xxx1 Rn
+        if hw1 & 0xFE10 != 0xF810 {
+            return None;
+        }
+
+        let rt = (hw2 >> 12) as usize;
+        return Some(LoadSite {
+            next: pc + 4,
+            reg: *LOAD_REGS.get(rt)?,
+            signed: hw1 & 0x0100 != 0,
+        });
+    }
+
+    let (rt, signed) = match hw1 >> 9 {
+        // LDRSB register
+        0b0101_011 => (hw1 & 7, true),
+        // LDRSH register
+        0b0101_111 => (hw1 & 7, true),
+        // LDR, LDRH, LDRB register
+        0b0101_100 | 0b0101_101 | 0b0101_110 => (hw1 & 7, false),
+        _ => match hw1 >> 11 {
+            // LDR, LDRB, LDRH immediate
+            0b01101 | 0b01111 | 0b10001 => (hw1 & 7, false),
+            // LDR literal and SP relative
+            0b01001 | 0b10011 => ((hw1 >> 8) & 7, false),
+            _ => return None,
+        },
+    };
+
+    Some(LoadSite { next: pc + 2, reg: *LOAD_REGS.get(rt as usize)?, signed })
+}
+
+/*
+ * Input of the current run and what the hooks know about the firmware
+ *
+ * @var ranges    - peripheral registers served from the input
+ * @var sites     - loads seen at translation, by their address
+ * @var stream    - bytes of the current testcase
+ * @var cursor    - bytes of the stream already handed out
+ * @var active    - set while a testcase runs, reads during boot reach the peripherals
+ * @var pending   - load that just read a peripheral and the size of the access
+ * @var exhausted - load that found the stream empty, it carries a breakpoint ending the run
+ * */
+struct Feed {
+    ranges: Vec<MmioRange>,
+    sites: BTreeMap<GuestAddr, LoadSite>,
+    stream: Vec<u8>,
+    cursor: usize,
+    active: bool,
+    pending: Option<(GuestAddr, usize)>,
+    exhausted: Option<GuestAddr>,
+}
+
+static FEED: Mutex<Feed> = Mutex::new(Feed {
+    ranges: Vec::new(),
+    sites: BTreeMap::new(),
+    stream: Vec::new(),
+    cursor: 0,
+    active: false,
+    pending: None,
+    exhausted: None,
+});
+
+// Finds the loads while a block is translated and hooks the instruction after each of them
+unsafe extern "C" fn gen_read(_data: u64, pc: GuestAddr, _addr: *mut TCGTemp, _info: MemAccessInfo) -> u64 {
+    let Some(qemu) = Qemu::get() else {
+        return NO_HOOK;
+    };
+
+    let mut feed = FEED.lock().unwrap();
+    if !feed.sites.contains_key(&pc) {
+        let mut insn = [0u8; 4];
+        if qemu.cpu_from_index(0).read_mem(pc, &mut insn).is_err() {
+            return NO_HOOK;
+        }
+        let hw1 = u16::from_le_bytes([insn[0], insn[1]]);
+        let hw2 = u16::from_le_bytes([insn[2], insn[3]]);
+
+        let Some(site) = decode_load(pc, hw1, hw2) else {
+            return NO_HOOK;
+        };
+
+        // Picked up when the next instruction is translated later in the same block, a block that
+        // already starts there is covered by exec_block
+        qemu.hooks().add_instruction_hooks(0u64, site.next, exec_after_load, false);
+        feed.sites.insert(pc, site);
+    }
+
+    pc as u64
+}
+
+fn on_read(id: u64, addr: GuestAddr, size: usize) {
+    let mut feed = FEED.lock().unwrap();
+    if feed.active && feed.ranges.iter().any(|range| range.contains(addr)) {
+        feed.pending = Some((id as GuestAddr, size));
+    }
+}
+
+unsafe extern "C" fn exec_read1(_data: u64, id: u64, _pc: GuestAddr, addr: GuestAddr) {
+    on_read(id, addr, 1);
+}
+
+unsafe extern "C" fn exec_read2(_data: u64, id: u64, _pc: GuestAddr, addr: GuestAddr) {
+    on_read(id, addr, 2);
+}
+
+unsafe extern "C" fn exec_read4(_data: u64, id: u64, _pc: GuestAddr, addr: GuestAddr) {
+    on_read(id, addr, 4);
+}
+
+// The id of a block is its start address, handed to the exec hook every time it runs
+unsafe extern "C" fn gen_block(_data: u64, pc: GuestAddr) -> u64 {
+    pc as u64
+}
+
+/*
+ * A load that ends its block is followed by a block starting at the next instruction
+ *
+ * Under -icount QEMU retranslates every block with a peripheral access so the access comes
+ * last, the block after it is often translated long before the load was seen
+ * */
+unsafe extern "C" fn exec_block(_data: u64, id: u64) {
+    feed_load(id as GuestAddr);
+}
+
+unsafe extern "C" fn exec_after_load(_data: u64, pc: GuestAddr) {
+    feed_load(pc);
+}
+
+// The load is done, its register gets the next bytes of the input instead of the peripheral value
+fn feed_load(next: GuestAddr) {
+    let Some(qemu) = Qemu::get() else {
+        return;
+    };
+
+    let mut guard = FEED.lock().unwrap();
+    let feed = &mut *guard;
+    let Some((pc, size)) = feed
+        .pending
+        .take_if(|(pc, _)| feed.sites.get(pc).is_some_and(|site| site.next == next))
+    else {
+        return;
+    };
+    let site = feed.sites[&pc];
+
+    let mut bytes = [0u8; 4];
+    let available = feed.stream.len() - feed.cursor;
+    if available < size {
+        // Out of input, the firmware ends up at this load again and stops there
+        if feed.exhausted.is_none() {
+            feed.exhausted = Some(pc);
+            qemu.set_breakpoint(pc);
+        }
+    } else {
+        bytes[..size].copy_from_slice(&feed.stream[feed.cursor..feed.cursor + size]);
+        feed.cursor += size;
+    }
+
+    let value = match (size, site.signed) {
+        (1, true) => bytes[0] as i8 as i32 as u32,
+        (2, true) => i16::from_le_bytes([bytes[0], bytes[1]]) as i32 as u32,
+        _ => u32::from_le_bytes(bytes),
+    };
+
+    let _ = qemu.cpu_from_index(0).write_reg(site.reg, value);
+}
+
+/*
+ * Serves reads of peripheral registers from the testcase
+ *
+ * The hooks live for the whole process, every run hands them a new stream
+ * */
+pub struct MmioFeed;
+
+impl MmioFeed {
+    // Has to happen before the firmware boots, loads translated earlier are never seen
+    pub fn install(qemu: Qemu, ranges: &[MmioRange]) {
+        FEED.lock().unwrap().ranges = ranges.to_vec();
+
+        for range in ranges {
+            info!("MMIO range {range} is served from the input");
+        }
+
+        qemu.hooks().add_read_hooks(
+            0u64,
+            Some(gen_read),
+            Some(exec_read1),
+            Some(exec_read2),
+            Some(exec_read4),
+            None,
+            None,
+        );
+        qemu.hooks().add_block_hooks(0u64, Some(gen_block), None, Some(exec_block));
+    }
+
+    pub fn start(stream: Vec<u8>) {
+        let mut feed = FEED.lock().unwrap();
+        feed.stream = stream;
+        feed.cursor = 0;
+        feed.pending = None;
+        feed.active = true;
+    }
+
+    // Next bytes of the stream for a reader other than the loads, e.g. the semihosting console
+    pub fn take(len: usize) -> Vec<u8> {
+        let mut feed = FEED.lock().unwrap();
+        if !feed.active {
+            return Vec::new();
+        }
+
+        let end = feed.cursor.saturating_add(len).min(feed.stream.len());
+        let bytes = feed.stream[feed.cursor..end].to_vec();
+        feed.cursor = end;
+        bytes
+    }
+
+    // Ends the run, returns the load that ran out of input if any and removes its breakpoint
+    pub fn stop(qemu: Qemu) -> Option<GuestAddr> {
+        let mut feed = FEED.lock().unwrap();
+        feed.active = false;
+        feed.pending = None;
+
+        let exhausted = feed.exhausted.take();
+        if let Some(pc) = exhausted {
+            qemu.remove_breakpoint(pc);
+        }
+        exhausted
+    }
+}
@@ -0,0 +1,165 @@
+use std::{borrow::Cow, num::NonZero};
+use libafl::{
+        corpus::Corpus, mutators::{MutationResult, Mutator}, random_corpus_id_with_disabled,
+        state::{HasCorpus, HasRand}, Error};
+use libafl_bolts::{rands::Rand, tuples::{tuple_list, tuple_list_type}, Named};
+
+use crate::input::{WordsInput, INTERESTING_WORDS};
+
+// Largest step of WordArithMutator in either direction
+const ARITH_MAX: u32 = 35;
+
+// Index of a random word, None for an empty input
+fn random_word<S: HasRand>(state: &mut S, input: &WordsInput) -> Option<usize> {
+    let len = NonZero::new(input.words().len())?;
+    Some(state.rand_mut().below(len))
+}
+
+// Flips one bit of a random word
+#[derive(Debug, Default)]
+pub struct WordFlipMutator;
+
+impl Named for WordFlipMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("WordFlipMutator");
+        &NAME
+    }
+}
+
+impl<S> Mutator<WordsInput, S> for WordFlipMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut WordsInput) -> Result<MutationResult, Error> {
+        let Some(idx) = random_word(state, input) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let bit = state.rand_mut().below(NonZero::new(u32::BITS as usize).unwrap());
+        input.words_mut()[idx] ^= 1 << bit;
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+// Adds or subtracts a small value to a random word, wrapping around
+#[derive(Debug, Default)]
+pub struct WordArithMutator;
+
+impl Named for WordArithMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("WordArithMutator");
+        &NAME
+    }
+}
+
+impl<S> Mutator<WordsInput, S> for WordArithMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut WordsInput) -> Result<MutationResult, Error> {
+        let Some(idx) = random_word(state, input) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let delta = 1 + state.rand_mut().below(NonZero::new(ARITH_MAX as usize).unwrap()) as u32;
+        let word = &mut input.words_mut()[idx];
+        *word = if state.rand_mut().coinflip(0.5) { word.wrapping_add(delta) } else { word.wrapping_sub(delta) };
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+// Overwrites a random word with one of INTERESTING_WORDS
+#[derive(Debug, Default)]
+pub struct WordInterestingMutator;
+
+impl Named for WordInterestingMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("WordInterestingMutator");
+        &NAME
+    }
+}
+
+impl<S> Mutator<WordsInput, S> for WordInterestingMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut WordsInput) -> Result<MutationResult, Error> {
+        let Some(idx) = random_word(state, input) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let value = *state.rand_mut().choose(&INTERESTING_WORDS).unwrap();
+        if input.words()[idx] == value {
+            return Ok(MutationResult::Skipped);
+        }
+        input.words_mut()[idx] = value;
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+/*
+ * Takes the words after a random split point from another testcase of the corpus
+ *
+ * The length of the input stays the same, words the other testcase does not have are kept
+ * */
+#[derive(Debug, Default)]
+pub struct WordSpliceMutator;
+
+impl Named for WordSpliceMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("WordSpliceMutator");
+        &NAME
+    }
+}
+
+impl<S> Mutator<WordsInput, S> for WordSpliceMutator
+where
+    S: HasCorpus<WordsInput> + HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut WordsInput) -> Result<MutationResult, Error> {
+        let id = random_corpus_id_with_disabled!(state.corpus(), state.rand_mut());
+
+        // Splicing with itself changes nothing
+        if *state.corpus().current() == Some(id) {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let other = {
+            let mut testcase = state.corpus().get_from_all(id)?.borrow_mut();
+            testcase.load_input(state.corpus())?.words().to_vec()
+        };
+
+        let len = input.words().len().min(other.len());
+        let Some(len) = NonZero::new(len) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let split = state.rand_mut().below(len);
+        if input.words()[split..len.get()] == other[split..len.get()] {
+            return Ok(MutationResult::Skipped);
+        }
+        input.words_mut()[split..len.get()].copy_from_slice(&other[split..len.get()]);
+
+        Ok(MutationResult::Mutated)
+    }
+}
+
+pub type WordMutationsType = tuple_list_type!(
+    WordFlipMutator,
+    WordArithMutator,
+    WordInterestingMutator,
+    WordSpliceMutator
+);
+
+// Every word mutator, the counterpart of havoc_mutations for WordsInput
+pub fn word_mutations() -> WordMutationsType {
+    tuple_list!(
+        WordFlipMutator,
+        WordArithMutator,
+        WordInterestingMutator,
+        WordSpliceMutator
+    )
+}
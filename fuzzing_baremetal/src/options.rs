@@ -0,0 +1,594 @@
+use std::{fmt, fs, path::{Path, PathBuf}, time::Duration};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use libafl_bolts::{core_affinity::Cores, current_nanos};
+use log::warn;
+use regex::bytes::Regex;
+use serde::Deserialize;
+
+use crate::{board::Board, input::WORD_SIZE, mmio::{MmioRange, DEFAULT_MMIO_RANGES}, strategy::{RestoreKind, StrategyKind},
+            watch::WatchSpec};
+
+/*
+ * Default Parameters
+ *
+ * Used when neither the command line nor the config file sets them
+ * */
+const DEFAULT_TIMEOUT_MS: u64 = 3000;
+const DEFAULT_TIMEOUT_INSNS: u64 = 10_000_000;
+const DEFAULT_BROKER_PORT: u16 = 1337;
+const DEFAULT_CORES: &str = "1";
+const DEFAULT_CORPUS_DIR: &str = "./corpus";
+const DEFAULT_CRASH_DIR: &str = "./crashes";
+const DEFAULT_HANG_DIR: &str = "./hangs";
+const DEFAULT_LOG_DIR: &str = "./logs";
+const DEFAULT_MAX_INPUT_SIZE: usize = 50;
+const DEFAULT_GDB_PORT: u16 = 1234;
+const DEFAULT_COVERAGE_DIR: &str = "./coverage";
+
+/*
+ * Command line of the fuzzer
+ *
+ * fuzzing_baremetal [OPTIONS] -- [QEMU ARGS]
+ * fuzzing_baremetal [OPTIONS] replay [REPLAY OPTIONS] <FILES>... -- [QEMU ARGS]
+ * fuzzing_baremetal [OPTIONS] coverage [COVERAGE OPTIONS] [DIRS]... -- [QEMU ARGS]
+ *
+ * Everything before `--` belongs to the fuzzer, everything after it is handed to QEMU untouched
+ * */
+#[derive(Parser, Debug)]
+#[command(about = "Fuzzes bare-metal firmware under QEMU system mode")]
+struct Cli {
+    /// TOML file with any of the options below, the command line wins over it
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// How testcases are run in the firmware
+    #[arg(long, value_enum)]
+    strategy: Option<StrategyKind>,
+
+    /// Board QEMU emulates e.g. mps2-an386, only the boards of the architecture the fuzzer
+    /// was built for, the first of them without it
+    #[arg(long, env = "BOARD")]
+    board: Option<String>,
+
+    /// How the low-level strategy gets the firmware back to main after each run
+    #[arg(long, value_enum)]
+    restore: Option<RestoreKind>,
+
+    /// Maximum time in milliseconds a testcase can run before it counts as a timeout
+    #[arg(long, value_name = "MS")]
+    timeout: Option<u64>,
+
+    /// Guest instructions a testcase can execute before it counts as a timeout, 0 leaves only
+    /// the wall clock --timeout
+    #[arg(long, value_name = "INSNS")]
+    timeout_insns: Option<u64>,
+
+    /// Seed of the random number generator, a random one is picked and printed without it
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Port of the broker all workers report to
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Cores to run worker processes on e.g. "all", "0-3" or "1,3"
+    #[arg(long)]
+    cores: Option<String>,
+
+    /// Interesting testcases are placed here, a restarted campaign resumes from it
+    #[arg(long, value_name = "DIR")]
+    corpus_dir: Option<PathBuf>,
+
+    /// Directories with initial testcases, can be given multiple times
+    #[arg(long = "seed-dir", value_name = "DIR")]
+    seed_dirs: Vec<PathBuf>,
+
+    /// Solutions are placed here
+    #[arg(long, value_name = "DIR")]
+    crash_dir: Option<PathBuf>,
+
+    /// Timed out testcases are placed here, one for every loop the firmware hangs in
+    #[arg(long, value_name = "DIR")]
+    hang_dir: Option<PathBuf>,
+
+    /// Every worker process logs into its own client-<id>.log here, rotated as it grows
+    #[arg(long, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
+
+    /// Largest testcase in u32 words that gets written into the input buffer of the firmware
+    #[arg(long, value_name = "WORDS")]
+    max_input_size: Option<usize>,
+
+    /// Firmware image that gets fuzzed
+    #[arg(long, env = "KERNEL")]
+    kernel: Option<PathBuf>,
+
+    /// qcow2 image attached to the board
+    #[arg(long, env = "DUMMY_IMG")]
+    virtual_disk: Option<PathBuf>,
+
+    /// Symbol of the buffer testcases are written into
+    #[arg(long, env = "FUZZ_INPUT")]
+    input_symbol: Option<String>,
+
+    /// Symbol where the fuzzer takes over and coverage begins
+    #[arg(long, env = "MAIN")]
+    main_symbol: Option<String>,
+
+    /// Symbol where a run ends successfully
+    #[arg(long, env = "BREAKPOINT")]
+    breakpoint_symbol: Option<String>,
+
+    /// Peripheral registers the mmio strategy serves from the testcase e.g. 0x40004000+0x1000,
+    /// can be given multiple times, the mps2 APB and GPIO without it
+    #[arg(long = "mmio", value_name = "RANGE")]
+    mmio: Vec<MmioRange>,
+
+    /// Leading words of every testcase that schedule NVIC interrupts, each one holds the IRQ
+    /// number in its low byte and the instruction count since main above it, low-level and mmio only,
+    /// not with --restore cpu-state or none
+    #[arg(long, value_name = "WORDS")]
+    irqs: Option<usize>,
+
+    /// Regex on the serial output of a run that makes it a solution e.g. ASSERT or "panic(ked)?",
+    /// can be given multiple times
+    #[arg(long = "serial-objective", value_name = "REGEX")]
+    serial_objectives: Vec<String>,
+
+    /// Memory only the given code may write during a run, REGION or REGION=CODE,CODE... where each is
+    /// a symbol or an address range e.g. TICKS=TIMER0_Handler, can be given multiple times
+    #[arg(long = "watch", value_name = "SPEC")]
+    watch: Vec<WatchSpec>,
+
+    /// Bytes right before and after the input buffer that no code may write during a run
+    #[arg(long, value_name = "BYTES")]
+    input_guard: Option<usize>,
+
+    /// Bytes at the low end of the stack, from __StackLimit up, that no code may write during a run
+    #[arg(long, value_name = "BYTES")]
+    stack_canary: Option<usize>,
+
+    /// Follow SP at every block and make a run that takes it below __StackLimit a solution,
+    /// the stack depth of every testcase is kept with it
+    #[arg(long)]
+    stack_check: bool,
+
+    /// Answer ARM semihosting calls of the firmware, SYS_EXIT ends a run and console output
+    /// is captured with the serial output
+    #[arg(long)]
+    semihosting: bool,
+
+    /// Console reads through semihosting get the testcase, implies --semihosting. With mmio they
+    /// share the stream with the peripheral reads, each byte goes to whichever reads it first
+    #[arg(long)]
+    semihosting_stdin: bool,
+
+    /// Arguments handed to QEMU e.g. -- -machine mps2-an385 -kernel kernel.elf,
+    /// without them --board boots --kernel
+    #[arg(last = true, value_name = "QEMU ARGS")]
+    qemu_args: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs saved testcases through the emulator once instead of fuzzing
+    Replay(ReplayCli),
+    /// Replays every testcase of the corpus and writes drcov files and an lcov report
+    Coverage(CoverageCli),
+}
+
+#[derive(Args, Debug)]
+struct ReplayCli {
+    /// Testcases written into the input buffer one after another
+    #[arg(required = true, value_name = "FILES")]
+    files: Vec<PathBuf>,
+
+    /// Open QEMU's gdb stub at the harness entry of the first file or at the first crash
+    #[arg(long, value_enum)]
+    gdb_at: Option<GdbAt>,
+
+    /// Port of the QEMU gdb stub used with --gdb-at
+    #[arg(long, default_value_t = DEFAULT_GDB_PORT)]
+    gdb_port: u16,
+
+    /// Arguments handed to QEMU, same as for fuzzing
+    #[arg(last = true, value_name = "QEMU ARGS")]
+    qemu_args: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct CoverageCli {
+    /// Directories with testcases, the corpus and seed directories when none are given
+    #[arg(value_name = "DIRS")]
+    dirs: Vec<PathBuf>,
+
+    /// Where the drcov files and coverage.info are written
+    #[arg(long, value_name = "DIR", default_value = DEFAULT_COVERAGE_DIR)]
+    out_dir: PathBuf,
+
+    /// Arguments handed to QEMU, same as for fuzzing
+    #[arg(last = true, value_name = "QEMU ARGS")]
+    qemu_args: Vec<String>,
+}
+
+// Same options as Cli, all of them optional, read from --config
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    strategy: Option<StrategyKind>,
+    board: Option<String>,
+    restore: Option<RestoreKind>,
+    timeout: Option<u64>,
+    timeout_insns: Option<u64>,
+    seed: Option<u64>,
+    port: Option<u16>,
+    cores: Option<String>,
+    corpus_dir: Option<PathBuf>,
+    seed_dirs: Option<Vec<PathBuf>>,
+    crash_dir: Option<PathBuf>,
+    hang_dir: Option<PathBuf>,
+    log_dir: Option<PathBuf>,
+    max_input_size: Option<usize>,
+    kernel: Option<PathBuf>,
+    virtual_disk: Option<PathBuf>,
+    input_symbol: Option<String>,
+    main_symbol: Option<String>,
+    breakpoint_symbol: Option<String>,
+    mmio: Option<Vec<MmioRange>>,
+    irqs: Option<usize>,
+    serial_objectives: Option<Vec<String>>,
+    watch: Option<Vec<WatchSpec>>,
+    input_guard: Option<usize>,
+    stack_canary: Option<usize>,
+    stack_check: Option<bool>,
+    semihosting: Option<bool>,
+    semihosting_stdin: Option<bool>,
+    qemu_args: Option<Vec<String>>,
+}
+
+/*
+ * Parameters of one fuzzing campaign
+ *
+ * @var strategy          - how testcases are run in the firmware
+ * @var board             - board QEMU emulates
+ * @var restore           - how the low level strategy resets the firmware between runs
+ * @var timeout           - maximum time a test case can run before timing out
+ * @var timeout_insns     - guest instructions a test case can execute before timing out, 0 for no limit
+ * @var seed              - seed of the random number generator, every client adds its id to it
+ * @var broker_port       - broker process for all fuzzer instances to connect to and coordinate together
+ * @var cores             - cores the worker processes are assigned to
+ * @var corpus_dir        - Interesting testcases are placed here
+ * @var seed_dirs         - initial testcases are loaded from here
+ * @var crash_dir         - successful testcases are placed here
+ * @var hang_dir          - timed out testcases are placed here, one per loop
+ * @var log_dir           - log files of the worker processes
+ * @var max_input_size    - largest testcase written into the firmware, in u32 words
+ * @var kernel            - firmware image
+ * @var virtual_disk      - optional qcow2 image for the board
+ * @var input_symbol      - buffer the testcases are written into
+ * @var main_symbol       - where coverage begins
+ * @var breakpoint_symbol - where coverage ends
+ * @var mmio              - peripheral registers the mmio strategy serves from the testcase
+ * @var irqs              - leading words of a testcase that schedule interrupts, 0 for none
+ * @var serial_objectives - patterns on the serial output that make a run a solution
+ * @var watch             - memory only the given code may write, a stray write makes a run a solution
+ * @var input_guard       - bytes around the input buffer no code may write, 0 for none
+ * @var stack_canary      - bytes at the low end of the stack no code may write, 0 for none
+ * @var stack_check       - follow SP against the stack bounds of the linker script
+ * @var semihosting       - answer semihosting calls of the firmware
+ * @var semihosting_stdin - serve the testcase to console reads through semihosting
+ * @var qemu_args         - QEMU command line, the program name comes first
+ * @var replay            - set when the replay or coverage subcommand was given instead of fuzzing
+ * */
+#[derive(Debug)]
+pub struct FuzzerOptions {
+    pub strategy: StrategyKind,
+    pub board: &'static Board,
+    pub restore: RestoreKind,
+    pub timeout: Duration,
+    pub timeout_insns: u64,
+    pub seed: u64,
+    pub broker_port: u16,
+    pub cores: Cores,
+    pub corpus_dir: PathBuf,
+    pub seed_dirs: Vec<PathBuf>,
+    pub crash_dir: PathBuf,
+    pub hang_dir: PathBuf,
+    pub log_dir: PathBuf,
+    pub max_input_size: usize,
+    pub kernel: Option<PathBuf>,
+    pub virtual_disk: Option<PathBuf>,
+    pub input_symbol: String,
+    pub main_symbol: String,
+    pub breakpoint_symbol: String,
+    pub mmio: Vec<MmioRange>,
+    pub irqs: usize,
+    pub serial_objectives: Vec<Regex>,
+    pub watch: Vec<WatchSpec>,
+    pub input_guard: usize,
+    pub stack_canary: usize,
+    pub stack_check: bool,
+    pub semihosting: bool,
+    pub semihosting_stdin: bool,
+    pub qemu_args: Vec<String>,
+    pub replay: Option<ReplayOptions>,
+}
+
+/*
+ * Parameters of the replay and coverage subcommands
+ *
+ * @var files        - testcases to run
+ * @var gdb_at       - where the firmware is handed over to gdb, None to just replay
+ * @var gdb_port     - port of the QEMU gdb stub
+ * @var coverage_dir - where block coverage is written, only set by the coverage subcommand
+ * */
+#[derive(Debug)]
+pub struct ReplayOptions {
+    pub files: Vec<PathBuf>,
+    pub gdb_at: Option<GdbAt>,
+    pub gdb_port: u16,
+    pub coverage_dir: Option<PathBuf>,
+}
+
+/*
+ * Where a replay stops and waits for gdb
+ *
+ * entry - at main with the first file written into the input buffer
+ * crash - where the first crashing file left the firmware
+ * */
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GdbAt {
+    Entry,
+    Crash,
+}
+
+impl fmt::Display for GdbAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GdbAt::Entry => write!(f, "entry"),
+            GdbAt::Crash => write!(f, "crash"),
+        }
+    }
+}
+
+impl FuzzerOptions {
+    // Parses the command line and merges it with the config file
+    pub fn parse() -> Self {
+        let cli = Cli::parse();
+
+        let file = match &cli.config {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .unwrap_or_else(|e| panic!("Failed to read config {}: {e}", path.display()));
+                toml::from_str(&content)
+                    .unwrap_or_else(|e| panic!("Failed to parse config {}: {e}", path.display()))
+            }
+            None => ConfigFile::default(),
+        };
+
+        let cores = cli.cores.or(file.cores).unwrap_or_else(|| DEFAULT_CORES.to_owned());
+
+        let kernel = cli.kernel.or(file.kernel);
+        let virtual_disk = cli.virtual_disk.or(file.virtual_disk);
+
+        let corpus_dir = cli.corpus_dir.or(file.corpus_dir).unwrap_or_else(|| DEFAULT_CORPUS_DIR.into());
+        let seed_dirs = if cli.seed_dirs.is_empty() { file.seed_dirs.unwrap_or_default() } else { cli.seed_dirs };
+
+        let board = match cli.board.or(file.board) {
+            Some(name) => Board::find(&name),
+            None => Board::default_board(),
+        };
+
+        // Only the strategies calling into QEMU by hand can stop the firmware for an interrupt
+        let strategy = cli.strategy.or(file.strategy).unwrap_or_default();
+        let irqs = cli.irqs.or(file.irqs).unwrap_or(0);
+        assert!(
+            irqs == 0 || matches!(strategy, StrategyKind::LowLevel | StrategyKind::Mmio),
+            "--irqs needs the low-level or mmio strategy, not {strategy}"
+        );
+
+        // Only a snapshot brings the NVIC back, an interrupt pended late in a run would be taken in the next
+        let restore = cli.restore.or(file.restore).unwrap_or_default();
+        assert!(
+            irqs == 0 || strategy != StrategyKind::LowLevel || matches!(restore, RestoreKind::SlowSnapshot | RestoreKind::FastSnapshot),
+            "--irqs needs a snapshot to restore the pending interrupts from, not --restore {restore}"
+        );
+
+        // Thumb loads and the NVIC only exist on the Cortex-M
+        assert!(
+            board.cortex_m || (strategy != StrategyKind::Mmio && irqs == 0),
+            "The mmio strategy and --irqs need a Cortex-M board, not {board}"
+        );
+
+        let serial_objectives = if cli.serial_objectives.is_empty() {
+            file.serial_objectives.unwrap_or_default()
+        } else {
+            cli.serial_objectives
+        };
+        let serial_objectives = serial_objectives
+            .iter()
+            .map(|pattern| Regex::new(pattern).unwrap_or_else(|e| panic!("Invalid serial objective '{pattern}': {e}")))
+            .collect();
+
+        // Flags on the command line can only turn semihosting on
+        let semihosting_stdin = cli.semihosting_stdin || file.semihosting_stdin.unwrap_or(false);
+        let semihosting = semihosting_stdin || cli.semihosting || file.semihosting.unwrap_or(false);
+
+        // QEMU arguments after `--` may follow the replay subcommand as well
+        let (cli_qemu_args, replay) = match cli.command {
+            Some(Command::Replay(replay)) => {
+                let args = if replay.qemu_args.is_empty() { cli.qemu_args } else { replay.qemu_args };
+                let options = ReplayOptions {
+                    files: replay.files,
+                    gdb_at: replay.gdb_at,
+                    gdb_port: replay.gdb_port,
+                    coverage_dir: None,
+                };
+                (args, Some(options))
+            }
+            Some(Command::Coverage(coverage)) => {
+                let args = if coverage.qemu_args.is_empty() { cli.qemu_args } else { coverage.qemu_args };
+                let dirs = if coverage.dirs.is_empty() {
+                    seed_dirs.iter().chain([&corpus_dir]).cloned().collect()
+                } else {
+                    coverage.dirs
+                };
+                let options = ReplayOptions {
+                    files: testcases_in(&dirs),
+                    gdb_at: None,
+                    gdb_port: DEFAULT_GDB_PORT,
+                    coverage_dir: Some(coverage.out_dir),
+                };
+                (args, Some(options))
+            }
+            None => (cli.qemu_args, None),
+        };
+
+        // QEMU wants the program name as its first argument
+        let mut qemu_args = vec![env!("CARGO_PKG_NAME").to_owned()];
+        if !cli_qemu_args.is_empty() {
+            qemu_args.extend(cli_qemu_args);
+        } else if let Some(args) = file.qemu_args {
+            qemu_args.extend(args);
+        } else if let Some(kernel) = &kernel {
+            qemu_args.extend(default_qemu_args(board, kernel, virtual_disk.as_ref()));
+        }
+
+        // gdb attaches to the stub once the entry or the crash is reached
+        if let Some(replay) = replay.as_ref().filter(|replay| replay.gdb_at.is_some()) {
+            qemu_args.push("-gdb".to_owned());
+            qemu_args.push(format!("tcp::{}", replay.gdb_port));
+        }
+
+        Self {
+            strategy,
+            board,
+            restore,
+            timeout: Duration::from_millis(cli.timeout.or(file.timeout).unwrap_or(DEFAULT_TIMEOUT_MS)),
+            timeout_insns: cli.timeout_insns.or(file.timeout_insns).unwrap_or(DEFAULT_TIMEOUT_INSNS),
+            seed: cli.seed.or(file.seed).unwrap_or_else(current_nanos),
+            broker_port: cli.port.or(file.port).unwrap_or(DEFAULT_BROKER_PORT),
+            cores: Cores::from_cmdline(&cores)
+                .unwrap_or_else(|e| panic!("Invalid cores '{cores}': {e:?}")),
+            corpus_dir,
+            seed_dirs,
+            crash_dir: cli.crash_dir.or(file.crash_dir).unwrap_or_else(|| DEFAULT_CRASH_DIR.into()),
+            hang_dir: cli.hang_dir.or(file.hang_dir).unwrap_or_else(|| DEFAULT_HANG_DIR.into()),
+            log_dir: cli.log_dir.or(file.log_dir).unwrap_or_else(|| DEFAULT_LOG_DIR.into()),
+            max_input_size: cli.max_input_size.or(file.max_input_size).unwrap_or(DEFAULT_MAX_INPUT_SIZE),
+            kernel,
+            virtual_disk,
+            input_symbol: cli.input_symbol.or(file.input_symbol).unwrap_or_else(|| "FUZZ_INPUT".to_owned()),
+            main_symbol: cli.main_symbol.or(file.main_symbol).unwrap_or_else(|| "main".to_owned()),
+            breakpoint_symbol: cli.breakpoint_symbol.or(file.breakpoint_symbol).unwrap_or_else(|| "BREAKPOINT".to_owned()),
+            mmio: match (cli.mmio.is_empty(), file.mmio) {
+                (false, _) => cli.mmio,
+                (true, Some(mmio)) => mmio,
+                (true, None) => DEFAULT_MMIO_RANGES.to_vec(),
+            },
+            irqs,
+            serial_objectives,
+            watch: if cli.watch.is_empty() { file.watch.unwrap_or_default() } else { cli.watch },
+            input_guard: cli.input_guard.or(file.input_guard).unwrap_or(0),
+            stack_canary: cli.stack_canary.or(file.stack_canary).unwrap_or(0),
+            stack_check: cli.stack_check || file.stack_check.unwrap_or(false),
+            semihosting,
+            semihosting_stdin,
+            qemu_args,
+            replay,
+        }
+    }
+
+    /*
+     * Directories the initial corpus is loaded from
+     *
+     * The corpus directory itself is part of it so a restarted campaign picks up
+     * its old queue, directories that do not exist yet are skipped
+     * */
+    pub fn seed_dirs(&self) -> Vec<PathBuf> {
+        self.seed_dirs
+            .iter()
+            .chain([&self.corpus_dir])
+            .filter(|dir| dir.is_dir())
+            .cloned()
+            .collect()
+    }
+
+    // Room the testcases take up in the input buffer of the firmware
+    pub fn max_input_bytes(&self) -> usize {
+        self.max_input_size * WORD_SIZE
+    }
+
+    // Firmware image, every strategy needs it one way or another
+    pub fn kernel(&self) -> &PathBuf {
+        self.kernel.as_ref().expect("No kernel given, use --kernel or the KERNEL env")
+    }
+}
+
+/*
+ * QEMU command line for the board when none was given
+ *
+ * There is no -serial, the fuzzer connects the serial port itself, see serial.rs. The
+ * virtual clock advances by a fixed 2^shift ns per instruction so timers fire at the same
+ * instruction on every run
+ *
+ * @param board        - board profile picked with --board
+ * @param kernel       - firmware image
+ * @param virtual_disk - qcow2 image attached to the board if any
+ * */
+fn default_qemu_args(board: &Board, kernel: &Path, virtual_disk: Option<&PathBuf>) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "-icount", "shift=0,align=off,sleep=off",
+        "-machine", board.machine,
+        "-cpu", board.cpu,
+        "-monitor", "null",
+        "-nographic",
+        "-snapshot",
+        "-S",
+    ]
+    .into_iter()
+    .chain(board.qemu_args.iter().copied())
+    .chain(["-kernel"])
+    .map(str::to_owned)
+    .collect();
+    args.push(kernel.display().to_string());
+
+    if let Some(disk) = virtual_disk {
+        args.push("-drive".to_owned());
+        args.push(format!("if=none,format=qcow2,file={}", disk.display()));
+    }
+
+    args
+}
+
+// Descriptions written next to solutions and hangs, <name>.report.txt, <name>.serial.txt and so on
+const SIDE_FILES: &[&str] = &[".report.", ".serial.", ".hang.", ".watch.", ".stack."];
+
+/*
+ * Testcases in the given directories, sorted so coverage runs are reproducible
+ *
+ * Hidden files (LibAFL metadata and lock files) and the SIDE_FILES next to a testcase are skipped
+ * */
+fn testcases_in(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            !name.starts_with('.') && !SIDE_FILES.iter().any(|suffix| name.contains(suffix))
+        })
+        .collect();
+    files.sort();
+
+    // A fresh campaign has nothing yet, its report lists every line unreached
+    if files.is_empty() {
+        warn!("No testcases found in {dirs:?}, the coverage report stays empty");
+    }
+    files
+}
@@ -0,0 +1,299 @@
+use std::slice;
+use libafl::{
+        corpus::InMemoryCorpus, events::SimpleEventManager, executors::{Executor, ExitKind, HasObservers},
+        feedbacks::ConstFeedback, inputs::Input, monitors::NopMonitor,
+        observers::{CanTrack, HitcountsMapObserver, ObserversTuple, VariableMapObserver},
+        schedulers::QueueScheduler, state::StdState, HasMetadata, StdFuzzer};
+use libafl_bolts::{impl_serdeany, ownedref::OwnedMutSlice, rands::StdRand,
+                   tuples::{tuple_list, Handled}};
+use libafl_qemu::{elf::EasyElf, modules::StdEdgeCoverageModule, sys::qemu_main_loop, Emulator, GuestAddr,
+                  GuestPhysAddr, Qemu, QemuExecutor, QemuExitReason};
+use libafl_targets::{edges_map_mut_ptr, EDGES_MAP_DEFAULT_SIZE, MAX_EDGES_FOUND};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{coverage::BlockCoverage, fault::CortexMFault, hang::HangLoop, icount::InsnCounter, input::WordsInput, irq::split_input, options::{FuzzerOptions, GdbAt, ReplayOptions}, report::{CrashContext, Symbolizer},
+            serial::{capture_path, capture_serial, SerialObserver}, strategy::{ExecutionStrategy, Strategy},
+            stack::{stack_bounds, StackMonitor, StackUsage}, watch::{WatchRegion, WatchViolation, Watchpoints}};
+
+/*
+ * What the strategies do differently while replaying
+ *
+ * @var record_pc     - leave a FinalPc in the state after every run
+ * @var stop_on_crash - skip the restore after a crash so gdb sees the firmware as it crashed
+ * */
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReplayMode {
+    pub record_pc: bool,
+    pub stop_on_crash: bool,
+}
+
+impl ReplayMode {
+    pub fn new(options: &FuzzerOptions) -> Self {
+        match &options.replay {
+            Some(replay) => Self { record_pc: true, stop_on_crash: replay.gdb_at == Some(GdbAt::Crash) },
+            None => Self::default(),
+        }
+    }
+}
+
+// Where a replayed run ended, before the strategy restored the firmware
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct FinalPc(pub GuestAddr);
+
+impl_serdeany!(FinalPc);
+
+/*
+ * Runs saved testcases through the emulator one by one
+ *
+ * The emulator is set up the same way as for fuzzing so a testcase behaves the same,
+ * for each file the exit kind, final PC, the number of edges hit, the instructions it
+ * executed and the serial output are printed. With the same --timeout-insns a testcase
+ * ends the same way every time
+ * */
+pub fn replay(options: &FuzzerOptions, replay: &ReplayOptions) {
+    info!("Replaying {} testcases with {}", replay.files.len(), options.strategy.label(options.restore));
+
+    // Creates a growable byte buffer that contains the binary of the elf file
+    let mut elf_buffer = Vec::new();
+    let elf = EasyElf::from_file(options.kernel(), &mut elf_buffer).unwrap();
+
+    // Nothing is run through a strategy, the firmware is just stopped at main
+    if replay.gdb_at == Some(GdbAt::Entry) {
+        debug_at_entry(options, replay, &elf);
+        return;
+    }
+
+    let mut strategy = Strategy::new(options.strategy, &elf, options);
+    let symbolizer = Symbolizer::new(&elf);
+
+    // Created an observeration channel to watch code coverage
+    let mut edges_observer = unsafe {
+        HitcountsMapObserver::new(VariableMapObserver::from_mut_slice(
+                "edges",
+                OwnedMutSlice::from_raw_parts_mut(edges_map_mut_ptr(), EDGES_MAP_DEFAULT_SIZE),
+                &raw mut MAX_EDGES_FOUND,
+        ))
+        .track_indices()
+    };
+
+    let emulator_mods = tuple_list!(
+        StdEdgeCoverageModule::builder()
+            .map_observer(edges_observer.as_mut())
+            .build()
+            .expect("Failed to intialize coverage map in QEMU")
+    );
+
+    // Serial output is captured the same way as well
+    let serial_path = capture_path();
+    let serial_observer = SerialObserver::new(serial_path.clone());
+    let serial_handle = serial_observer.handle();
+
+    // Initialize QEMU Emulator
+    let mut emu = Emulator::builder()
+        .qemu_parameters(capture_serial(&options.qemu_args, &serial_path))
+        .modules(emulator_mods)
+        .build()
+        .expect("Failed to call QEMU emulator");
+
+    // Block and write hooks go in before the strategy lets the firmware run for the first time
+    InsnCounter::install(emu.qemu(), options);
+    Watchpoints::install(emu.qemu(), WatchRegion::resolve_all(&elf, options));
+    StackMonitor::install(emu.qemu(), options.stack_check.then(|| stack_bounds(&elf)));
+    let mut coverage = replay.coverage_dir.as_ref().map(|dir| {
+        BlockCoverage::install(emu.qemu());
+        BlockCoverage::new(dir, options.kernel(), &elf)
+    });
+
+    strategy.prepare(&mut emu);
+
+    let mut harness = |
+        emulator: &mut Emulator<_,_,_,_,_,_,_,>,
+        state: &mut _,
+        input: &WordsInput| strategy.run(emulator, state, input);
+
+    // Nothing is kept, the fuzzer only exists because the executor wants one
+    let mut feedback = ConstFeedback::new(false);
+    let mut objective = ConstFeedback::new(false);
+
+    let mut state = StdState::new(
+        StdRand::with_seed(options.seed),
+        InMemoryCorpus::<WordsInput>::new(),
+        InMemoryCorpus::new(),
+        &mut feedback,
+        &mut objective
+    )
+    .expect("Failed to create state");
+
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mut mgr = SimpleEventManager::new(NopMonitor::new());
+
+    let mut executor = QemuExecutor::new(
+        emu,
+        &mut harness,
+        tuple_list!(edges_observer, serial_observer),
+        &mut fuzzer,
+        &mut state,
+        &mut mgr,
+        options.timeout
+    )
+    .expect("Failed to start QEMU executor");
+
+    executor.break_on_timeout();
+
+    for file in &replay.files {
+        let input = WordsInput::from_file(file)
+            .unwrap_or_else(|e| panic!("Failed to read testcase {}: {e}", file.display()));
+
+        if let Some(coverage) = &coverage {
+            coverage.start_testcase();
+        }
+
+        // Resets the coverage map like the fuzzer does before every execution
+        executor.observers_mut().pre_exec_all(&mut state, &input).unwrap();
+        let exit_kind = executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .expect("Failed to run testcase");
+        executor.observers_mut().post_exec_all(&mut state, &input, &exit_kind).unwrap();
+
+        let edges = unsafe { slice::from_raw_parts(edges_map_mut_ptr(), EDGES_MAP_DEFAULT_SIZE) }
+            .iter()
+            .filter(|hits| **hits != 0)
+            .count();
+
+        let final_pc = state.metadata_map_mut().remove::<FinalPc>().map(|pc| pc.0);
+        let fault = state.metadata_map_mut().remove::<CortexMFault>();
+        let hang = state.metadata_map_mut().remove::<HangLoop>();
+        let violation = state.metadata_map_mut().remove::<WatchViolation>();
+        let stack = state.metadata_map_mut().remove::<StackUsage>();
+        state.metadata_map_mut().remove::<CrashContext>();
+
+        let location = match final_pc {
+            Some(pc) => format!("{:#X} {}", pc, symbolizer.symbolize(pc).unwrap_or_default()),
+            None => "unknown".to_owned(),
+        };
+        println!(
+            "[REPLAY] {}: {exit_kind:?}, final PC {location}, {edges} edges, {} instructions",
+            file.display(),
+            InsnCounter::executed()
+        );
+        if let Some(fault) = fault {
+            println!("[REPLAY]   {fault}");
+        }
+        if let Some(stack) = stack {
+            println!("[REPLAY]   {}", stack.to_text(&symbolizer));
+        }
+        if let Some(violation) = violation {
+            println!("[REPLAY]   {}", violation.to_text(&symbolizer));
+        }
+        if let Some(hang) = hang {
+            for line in hang.to_text(&symbolizer).lines() {
+                println!("[REPLAY]   {line}");
+            }
+        }
+
+        let serial = String::from_utf8_lossy(executor.observers()[&serial_handle].output()).into_owned();
+        for line in serial.lines() {
+            println!("[REPLAY]   | {line}");
+        }
+        if let Some(pattern) = options.serial_objectives.iter().find(|pattern| pattern.is_match(serial.as_bytes())) {
+            println!("[REPLAY]   serial output matches '{pattern}'");
+        }
+        if let Some(coverage) = &mut coverage {
+            let blocks = coverage.finish_testcase(file).expect("Failed to write drcov file");
+            println!("[REPLAY]   {blocks} blocks");
+        }
+
+        if exit_kind == ExitKind::Crash && replay.gdb_at == Some(GdbAt::Crash) {
+            let qemu = Qemu::get().expect("QEMU is not running");
+
+            // QEMU would stop on the breakpoint the run ended on again right away
+            if let Some(pc) = final_pc {
+                qemu.remove_breakpoint(pc);
+            }
+
+            // gdb may keep the firmware running for good, the report covers the files so far
+            write_lcov(coverage.as_ref());
+            wait_for_gdb(qemu, options, replay);
+            return;
+        }
+    }
+
+    write_lcov(coverage.as_ref());
+}
+
+// lcov report of every file replayed, if --coverage-dir was given
+fn write_lcov(coverage: Option<&BlockCoverage>) {
+    if let Some(coverage) = coverage {
+        let lcov = coverage.write_lcov().expect("Failed to write lcov report");
+        info!("Coverage written to {}, view it with: genhtml {} -o <dir>", lcov.display(), lcov.display());
+    }
+}
+
+/*
+ * Boots the firmware up to main, writes the first file into the input buffer and
+ * hands it over to gdb before a single instruction of the harness ran
+ *
+ * The serial output goes into the capture file of this process, tail it while debugging
+ * */
+fn debug_at_entry(options: &FuzzerOptions, replay: &ReplayOptions, elf: &EasyElf) {
+    if replay.files.len() > 1 {
+        info!("Only the first file is used with --gdb-at entry");
+    }
+
+    let file = &replay.files[0];
+    let input = WordsInput::from_file(file)
+        .unwrap_or_else(|e| panic!("Failed to read testcase {}: {e}", file.display()));
+
+    let input_addr = elf
+        .resolve_symbol(&options.input_symbol, 0)
+        .expect("having trouble finding the input buffer in binary") as GuestPhysAddr;
+    let main_addr = elf
+        .resolve_symbol(&options.main_symbol, 0)
+        .expect("having trouble finding main function in binary");
+
+    let serial_path = capture_path();
+    let qemu = Qemu::init(capture_serial(&options.qemu_args, &serial_path)).expect("Failed to start QEMU");
+    println!("[REPLAY] Serial output goes to {}", serial_path.display());
+
+    qemu.set_breakpoint(main_addr);
+    unsafe {
+        match qemu.run() {
+            Ok(QemuExitReason::Breakpoint(_)) => {}
+            e => panic!("Firmware did not reach main: {e:?}"),
+        }
+    }
+    qemu.remove_breakpoint(main_addr);
+
+    // Interrupts are not injected here, the schedule is skipped
+    let (_, buf) = split_input(&input, options.irqs);
+    let buf = &buf[..buf.len().min(options.max_input_bytes())];
+    unsafe { qemu.write_phys_mem(input_addr, buf) };
+
+    println!("[REPLAY] {} written into {} at main", file.display(), options.input_symbol);
+    wait_for_gdb(qemu, options, replay);
+}
+
+/*
+ * Keeps QEMU alive so gdb can attach to its stub
+ *
+ * The stub is only served while the QEMU main loop runs. It first runs without starting
+ * the CPU, so the firmware stays where it stopped until gdb attaches and continues. After
+ * that QEMU keeps running the firmware until gdb ends it
+ * */
+fn wait_for_gdb(qemu: Qemu, options: &FuzzerOptions, replay: &ReplayOptions) {
+    println!(
+        "[REPLAY] Stopped at the {}, attach with: gdb-multiarch {} -ex 'target remote :{}'",
+        replay.gdb_at.map(|at| at.to_string()).unwrap_or_default(),
+        options.kernel().display(),
+        replay.gdb_port
+    );
+    unsafe { qemu_main_loop() };
+
+    loop {
+        match unsafe { qemu.run() } {
+            Ok(QemuExitReason::End(_)) | Err(_) => break,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::{borrow::Cow, fmt::Write as _, fs, path::{Path, PathBuf}};
+use libafl::{
+        corpus::Testcase, executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer}, inputs::Input, Error, HasMetadata};
+use libafl_bolts::{impl_serdeany, Named};
+use libafl_qemu::{elf::EasyElf, GuestAddr, GuestReg, Qemu, Regs};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{board::{REGISTERS, RETURN_REG}, fault::CortexMFault, icount::InsnCounter};
+
+// How many words above SP are scanned for return addresses
+const STACK_SCAN_WORDS: usize = 256;
+
+// Size of a word on the stack of the board
+const WORD_BYTES: usize = size_of::<GuestReg>();
+
+/*
+ * CPU state of a crashing run, captured before the strategy restores the firmware
+ *
+ * @var registers - every register of the board's report list that could be read
+ * @var stack     - words from SP upwards, as far as they could be read
+ * @var insns     - guest instructions the run executed, 0 when they are not counted
+ * */
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrashContext {
+    pub registers: Vec<(String, GuestReg)>,
+    pub stack: Vec<GuestReg>,
+    pub insns: u64,
+}
+
+impl_serdeany!(CrashContext);
+
+impl CrashContext {
+    pub fn capture(qemu: Qemu) -> Self {
+        let cpu = qemu.cpu_from_index(0);
+
+        let registers = REGISTERS
+            .iter()
+            .filter_map(|(name, reg)| cpu.read_reg(*reg).ok().map(|value: GuestReg| ((*name).to_owned(), value)))
+            .collect();
+
+        let mut stack = Vec::new();
+        let sp: Result<GuestAddr, _> = cpu.read_reg(Regs::Sp);
+        if let Ok(sp) = sp {
+            // A corrupted SP may sit right below the end of the address space
+            for i in 0..STACK_SCAN_WORDS {
+                let Some(addr) = sp.checked_add((i * WORD_BYTES) as GuestAddr) else {
+                    break;
+                };
+                let mut buf = [0u8; WORD_BYTES];
+                if cpu.read_mem(addr, &mut buf).is_err() {
+                    break;
+                }
+                stack.push(GuestReg::from_le_bytes(buf));
+            }
+        }
+
+        Self { registers, stack, insns: InsnCounter::executed() }
+    }
+
+    fn register(&self, name: &str) -> Option<GuestReg> {
+        self.registers.iter().find(|(n, _)| n == name).map(|(_, value)| *value)
+    }
+}
+
+/*
+ * Function symbols of the kernel, owned so it can outlive the ELF buffer
+ *
+ * @var functions - start, end and name of every function symbol sorted by start
+ * */
+#[derive(Clone, Debug, Default)]
+pub struct Symbolizer {
+    functions: Vec<(GuestAddr, GuestAddr, String)>,
+}
+
+impl Symbolizer {
+    pub fn new(elf: &EasyElf) -> Self {
+        let goblin = elf.goblin();
+
+        let mut functions: Vec<_> = goblin
+            .syms
+            .iter()
+            .filter(|sym| sym.is_function() && sym.st_size > 0)
+            .filter_map(|sym| {
+                let name = goblin.strtab.get_at(sym.st_name)?;
+                let start = (sym.st_value as GuestAddr) & !1;
+                Some((start, start + sym.st_size as GuestAddr, name.to_owned()))
+            })
+            .collect();
+        functions.sort_unstable_by_key(|(start, _, _)| *start);
+
+        Self { functions }
+    }
+
+    // Start and end of every function
+    pub fn functions(&self) -> impl Iterator<Item = (GuestAddr, GuestAddr)> + '_ {
+        self.functions.iter().map(|(start, end, _)| (*start, *end))
+    }
+
+    // name+offset of the function containing addr, the Thumb bit is ignored
+    pub fn symbolize(&self, addr: GuestAddr) -> Option<String> {
+        let addr = addr & !1;
+        self.functions
+            .iter()
+            .find(|(start, end, _)| (*start..*end).contains(&addr))
+            .map(|(start, _, name)| format!("{name}+{:#x}", addr - start))
+    }
+}
+
+/*
+ * One frame of the unwind
+ *
+ * @var addr   - PC, LR or a return address found on the stack
+ * @var symbol - function it belongs to
+ * */
+#[derive(Serialize, Clone, Debug)]
+pub struct Frame {
+    pub addr: GuestAddr,
+    pub symbol: Option<String>,
+}
+
+/*
+ * What gets written next to a solution
+ *
+ * The unwind is a heuristic, PC and the return address register first then every word on
+ * the stack that points into a function of the kernel, with the Thumb bit set on ARM
+ *
+ * @var name      - file name of the solution in the crash directory
+ * @var exit_kind - how the run ended
+ * @var seed      - seed of the client that found it
+ * @var insns     - guest instructions the run executed until it stopped
+ * @var fault     - decoded Cortex-M fault if the run stopped in a fault handler
+ * @var registers - registers at the crash
+ * @var frames    - symbolized unwind
+ * */
+#[derive(Serialize, Clone, Debug)]
+pub struct CrashReport {
+    pub name: String,
+    pub exit_kind: String,
+    pub seed: u64,
+    pub insns: u64,
+    pub fault: Option<CortexMFault>,
+    pub registers: Vec<(String, GuestReg)>,
+    pub frames: Vec<Frame>,
+}
+
+impl CrashReport {
+    pub fn new(
+        name: String,
+        exit_kind: &ExitKind,
+        seed: u64,
+        fault: Option<CortexMFault>,
+        context: &CrashContext,
+        symbolizer: &Symbolizer,
+    ) -> Self {
+        let frame = |addr: GuestAddr| Frame { addr: addr & !1, symbol: symbolizer.symbolize(addr) };
+
+        // The stacked PC of a fault is where the firmware really was
+        let mut frames: Vec<Frame> = fault
+            .as_ref()
+            .and_then(|fault| fault.pc)
+            .into_iter()
+            .chain(context.register("pc").map(|pc| pc as GuestAddr))
+            .chain(context.register(RETURN_REG.0).map(|lr| lr as GuestAddr))
+            .map(frame)
+            .collect();
+
+        frames.extend(
+            context
+                .stack
+                .iter()
+                .map(|word| *word as GuestAddr)
+                // Thumb return addresses have bit 0 set, anything else could be one
+                .filter(|word| !cfg!(feature = "arm") || *word & 1 == 1)
+                .filter(|word| symbolizer.symbolize(*word).is_some())
+                .map(frame),
+        );
+
+        Self {
+            name,
+            exit_kind: format!("{exit_kind:?}"),
+            seed,
+            insns: context.insns,
+            fault,
+            registers: context.registers.clone(),
+            frames,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        let _ = writeln!(text, "Crash report for {}", self.name);
+        let _ = writeln!(text, "Exit kind: {}", self.exit_kind);
+        let _ = writeln!(text, "Seed: {}", self.seed);
+        let _ = writeln!(text, "Instructions: {}", self.insns);
+        if let Some(fault) = &self.fault {
+            let _ = writeln!(text, "Fault: {fault}");
+        }
+
+        let _ = writeln!(text, "\nRegisters:");
+        for (name, value) in &self.registers {
+            let _ = writeln!(text, "  {name:<5} {value:#010x}");
+        }
+
+        let _ = writeln!(text, "\nStack:");
+        for (i, frame) in self.frames.iter().enumerate() {
+            let symbol = frame.symbol.as_deref().unwrap_or("??");
+            let _ = writeln!(text, "  #{i:<3} {:#010x} {symbol}", frame.addr);
+        }
+
+        text
+    }
+
+    // Writes <name>.report.json and <name>.report.txt into dir
+    pub fn write(&self, dir: &Path) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::serialize(format!("Failed to serialize crash report: {e}")))?;
+
+        fs::write(dir.join(format!("{}.report.json", self.name)), json)?;
+        fs::write(dir.join(format!("{}.report.txt", self.name)), self.to_text())?;
+
+        Ok(())
+    }
+}
+
+/*
+ * Writes a crash report for every solution that has a captured CrashContext
+ *
+ * Has to come after CortexMFaultFeedback so the fault is already on the testcase
+ *
+ * @var crash_dir  - where the solutions and their reports go
+ * @var symbolizer - functions of the kernel
+ * @var seed       - seed of this client, written into every report
+ * @var context    - context of the current execution
+ * @var exit_kind  - exit kind of the current execution
+ * */
+#[derive(Debug)]
+pub struct CrashReportFeedback {
+    crash_dir: PathBuf,
+    symbolizer: Symbolizer,
+    seed: u64,
+    context: Option<CrashContext>,
+    exit_kind: ExitKind,
+}
+
+impl CrashReportFeedback {
+    pub fn new(crash_dir: PathBuf, symbolizer: Symbolizer, seed: u64) -> Self {
+        Self {
+            crash_dir,
+            symbolizer,
+            seed,
+            context: None,
+            exit_kind: ExitKind::Ok,
+        }
+    }
+}
+
+impl Named for CrashReportFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("CrashReportFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for CrashReportFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for CrashReportFeedback
+where
+    I: Input,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        self.context = state.metadata_map_mut().remove::<CrashContext>().map(|context| *context);
+        self.exit_kind = *exit_kind;
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let Some(context) = self.context.take() else {
+            return Ok(());
+        };
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+
+        // Same name the solution gets in the crash directory
+        let name = input.generate_name(None);
+        let fault = testcase.metadata::<CortexMFault>().ok().cloned();
+
+        let report = CrashReport::new(name, &self.exit_kind, self.seed, fault, &context, &self.symbolizer);
+        report.write(&self.crash_dir)?;
+        info!("Crash report written for {}", report.name);
+
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.context = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+use std::{collections::BTreeSet, fs::OpenOptions, io::Write, ops::Range};
+use libafl::executors::ExitKind;
+use libafl_qemu::{elf::EasyElf, GuestAddr, GuestReg, Qemu, Regs, CPU};
+use log::{info, warn};
+
+use crate::{board::{ARG_REGS, SEMIHOSTING_CALL, SEMIHOSTING_STEP, SEMIHOSTING_TRAP}, options::FuzzerOptions,
+            mmio::MmioFeed, serial::{capture_path, MAX_OUTPUT_BYTES}};
+
+// Program header type of a loadable segment and the flag of an executable one
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+
+// Size of a field in a parameter block, the register size of the board
+const WORD_BYTES: usize = size_of::<GuestReg>();
+
+/*
+ * Semihosting operations, r0 / x0 / a0 on entry
+ *
+ * Anything not listed is answered with -1
+ * */
+const SYS_OPEN: GuestReg = 0x01;
+const SYS_CLOSE: GuestReg = 0x02;
+const SYS_WRITEC: GuestReg = 0x03;
+const SYS_WRITE0: GuestReg = 0x04;
+const SYS_WRITE: GuestReg = 0x05;
+const SYS_READ: GuestReg = 0x06;
+const SYS_READC: GuestReg = 0x07;
+const SYS_ISERROR: GuestReg = 0x08;
+const SYS_ISTTY: GuestReg = 0x09;
+const SYS_SEEK: GuestReg = 0x0A;
+const SYS_FLEN: GuestReg = 0x0C;
+const SYS_CLOCK: GuestReg = 0x10;
+const SYS_TIME: GuestReg = 0x11;
+const SYS_ERRNO: GuestReg = 0x13;
+const SYS_GET_CMDLINE: GuestReg = 0x15;
+const SYS_HEAPINFO: GuestReg = 0x16;
+const SYS_EXIT: GuestReg = 0x18;
+const SYS_EXIT_EXTENDED: GuestReg = 0x20;
+
+// Reason code of SYS_EXIT for a program that ended normally
+const ADP_STOPPED_APPLICATION_EXIT: GuestReg = 0x20026;
+
+// Returned for failed calls
+const FAILED: GuestReg = GuestReg::MAX;
+
+// errno of a file other than the console
+const ENOENT: GuestReg = 2;
+
+// Longest file name and SYS_WRITE0 string read from the firmware
+const MAX_STRING_SIZE: GuestAddr = 0x1000;
+
+/*
+ * Contents of the :semihosting-features file, newlib reads it to find out if SYS_EXIT_EXTENDED
+ * is there, without it exit codes are lost on 32 bit targets
+ * */
+const FEATURES: [u8; 5] = [0x53, 0x48, 0x46, 0x42, 0x01];
+
+// Handles of the files the firmware can open, 0 is never handed out
+const HANDLE_STDIN: GuestReg = 1;
+const HANDLE_STDOUT: GuestReg = 2;
+const HANDLE_STDERR: GuestReg = 3;
+const HANDLE_FEATURES: GuestReg = 4;
+
+// What a semihosting call means for the run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SemihostingCall {
+    // Answered, the firmware goes on after the trap
+    Handled,
+    // SYS_EXIT, the run ends with an exit kind from its reason and exit code
+    Exit(ExitKind),
+}
+
+/*
+ * Answers semihosting calls of firmware built against newlib's rdimon or similar
+ *
+ * QEMU only does semihosting with -semihosting, which ends the whole emulator on SYS_EXIT
+ * and prints to its own console. Instead every trap in the code of the firmware gets a
+ * breakpoint and the call is answered here. Output goes into the serial capture so it is
+ * matched against --serial-objective and saved next to solutions, the console reads the
+ * testcase with --semihosting-stdin and hits end of file right away without it. Only the
+ * console exists, opening any other file fails
+ *
+ * @var enabled      - set with --semihosting
+ * @var serve_stdin  - set with --semihosting-stdin
+ * @var code         - executable segments of the firmware, searched for traps
+ * @var traps        - instruction of every trap the call happens on
+ * @var stdin        - bytes the console reads in the current run
+ * @var cursor       - bytes of stdin already read
+ * @var shared       - the console reads the MMIO stream instead, at the cursor of the MMIO feed
+ * @var features_pos - read position in :semihosting-features
+ * @var errno        - result of SYS_ERRNO
+ * */
+#[derive(Clone, Debug)]
+pub struct Semihosting {
+    enabled: bool,
+    serve_stdin: bool,
+    code: Vec<Range<GuestAddr>>,
+    traps: BTreeSet<GuestAddr>,
+    stdin: Vec<u8>,
+    cursor: usize,
+    shared: bool,
+    features_pos: usize,
+    errno: GuestReg,
+}
+
+impl Semihosting {
+    pub fn new(elf: &EasyElf, options: &FuzzerOptions) -> Self {
+        let code = elf
+            .goblin()
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == PT_LOAD && ph.p_flags & PF_X != 0)
+            .map(|ph| ph.p_vaddr as GuestAddr..(ph.p_vaddr + ph.p_filesz) as GuestAddr)
+            .collect();
+
+        Self {
+            enabled: options.semihosting,
+            serve_stdin: options.semihosting_stdin,
+            code,
+            traps: BTreeSet::new(),
+            stdin: Vec::new(),
+            cursor: 0,
+            shared: false,
+            features_pos: 0,
+            errno: 0,
+        }
+    }
+
+    /*
+     * Finds the traps once the firmware is loaded, the strategy puts a breakpoint on each of them
+     *
+     * Returns nothing without --semihosting. Data in the code that looks like a trap only gets a
+     * breakpoint that is never hit
+     * */
+    pub fn install(&mut self, qemu: Qemu) -> Vec<GuestAddr> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        let cpu = qemu.cpu_from_index(0);
+        for range in &self.code {
+            let mut code = vec![0u8; (range.end - range.start) as usize];
+            if cpu.read_mem(range.start, &mut code).is_err() {
+                warn!("Failed to read code at {:#X}, semihosting calls in it are missed", range.start);
+                continue;
+            }
+
+            let traps = (0..code.len())
+                .step_by(SEMIHOSTING_STEP)
+                .filter(|offset| code[*offset..].starts_with(SEMIHOSTING_TRAP))
+                .map(|offset| range.start + offset as GuestAddr + SEMIHOSTING_CALL.0);
+            self.traps.extend(traps);
+        }
+
+        info!("{} semihosting calls found in the firmware", self.traps.len());
+        self.traps.iter().copied().collect()
+    }
+
+    // Starts a run, stdin is what the console reads with --semihosting-stdin
+    pub fn start(&mut self, stdin: &[u8]) {
+        self.stdin.clear();
+        if self.serve_stdin {
+            self.stdin.extend_from_slice(stdin);
+        }
+        self.cursor = 0;
+        self.shared = false;
+        self.features_pos = 0;
+        self.errno = 0;
+    }
+
+    // Starts a run whose console reads take their bytes from the MMIO feed, see MmioFeed::take
+    pub fn start_shared(&mut self) {
+        self.start(&[]);
+        self.shared = self.serve_stdin;
+    }
+
+    // Next bytes of the console, fewer than asked for at the end of the input
+    fn read_stdin(&mut self, len: usize) -> Vec<u8> {
+        if self.shared {
+            return MmioFeed::take(len);
+        }
+
+        let bytes = &self.stdin[self.cursor.min(self.stdin.len())..];
+        let bytes = bytes[..bytes.len().min(len)].to_vec();
+        self.cursor += bytes.len();
+        bytes
+    }
+
+    /*
+     * Answers the semihosting call the firmware is stopped at
+     *
+     * Returns None if it did not stop at a trap. The firmware is moved past the trap for
+     * every call but SYS_EXIT, which leaves it where it called
+     * */
+    pub fn handle_stop(&mut self, qemu: Qemu) -> Option<SemihostingCall> {
+        if self.traps.is_empty() {
+            return None;
+        }
+
+        let cpu = qemu.cpu_from_index(0);
+        let pc: GuestAddr = cpu.read_reg(Regs::Pc).ok()?;
+        if !self.traps.contains(&pc) {
+            return None;
+        }
+
+        let op: GuestReg = cpu.read_reg(ARG_REGS[0]).expect("Failed to read the semihosting operation");
+        let param: GuestReg = cpu.read_reg(ARG_REGS[1]).expect("Failed to read the semihosting parameter");
+
+        if op == SYS_EXIT || op == SYS_EXIT_EXTENDED {
+            return Some(SemihostingCall::Exit(exit_kind(&cpu, op, param)));
+        }
+
+        let result = self.answer(&cpu, op, param as GuestAddr);
+        cpu.write_reg(ARG_REGS[0], result).expect("Failed to write the semihosting result");
+        cpu.write_reg(Regs::Pc, pc + SEMIHOSTING_CALL.1).expect("Failed to return from the semihosting call");
+
+        Some(SemihostingCall::Handled)
+    }
+
+    // Result of every call but SYS_EXIT, param is the parameter block for most of them
+    fn answer(&mut self, cpu: &CPU, op: GuestReg, param: GuestAddr) -> GuestReg {
+        match op {
+            SYS_OPEN => {
+                let name = read_string(cpu, field(cpu, param, 0) as GuestAddr, Some(field(cpu, param, 2)));
+                let mode = field(cpu, param, 1);
+                match name.as_slice() {
+                    // Modes 0-3 read, 4-7 write and 8-11 append
+                    b":tt" => match mode {
+                        0..=3 => HANDLE_STDIN,
+                        4..=7 => HANDLE_STDOUT,
+                        _ => HANDLE_STDERR,
+                    },
+                    b":semihosting-features" => HANDLE_FEATURES,
+                    _ => {
+                        self.errno = ENOENT;
+                        FAILED
+                    }
+                }
+            }
+            SYS_CLOSE => 0,
+            SYS_WRITEC => {
+                capture(&read_string(cpu, param, Some(1)));
+                0
+            }
+            SYS_WRITE0 => {
+                capture(&read_string(cpu, param, None));
+                0
+            }
+            SYS_WRITE => {
+                let (handle, buf, len) = (field(cpu, param, 0), field(cpu, param, 1), field(cpu, param, 2));
+                if handle != HANDLE_STDOUT && handle != HANDLE_STDERR {
+                    return len;
+                }
+                // The length comes from the firmware, the serial capture drops anything past this anyway
+                let mut bytes = vec![0u8; (len as u64).min(MAX_OUTPUT_BYTES) as usize];
+                if cpu.read_mem(buf as GuestAddr, &mut bytes).is_err() {
+                    return len;
+                }
+                capture(&bytes);
+                len - bytes.len() as GuestReg
+            }
+            SYS_READ => {
+                let (handle, buf, len) = (field(cpu, param, 0), field(cpu, param, 1), field(cpu, param, 2));
+                let bytes = match handle {
+                    HANDLE_STDIN => self.read_stdin(len as usize),
+                    HANDLE_FEATURES => {
+                        let bytes = &FEATURES[self.features_pos.min(FEATURES.len())..];
+                        let bytes = bytes[..bytes.len().min(len as usize)].to_vec();
+                        self.features_pos += bytes.len();
+                        bytes
+                    }
+                    _ => return len,
+                };
+
+                // Returns how many bytes were not read, all of them at the end of the file
+                if cpu.write_mem(buf as GuestAddr, &bytes).is_err() {
+                    return len;
+                }
+                len - bytes.len() as GuestReg
+            }
+            SYS_READC => match self.read_stdin(1).first() {
+                Some(byte) => *byte as GuestReg,
+                None => FAILED,
+            },
+            // Errors are negative
+            SYS_ISERROR => GuestReg::from(field(cpu, param, 0).leading_ones() > 0),
+            SYS_ISTTY => GuestReg::from(matches!(field(cpu, param, 0), HANDLE_STDIN | HANDLE_STDOUT | HANDLE_STDERR)),
+            SYS_SEEK => match field(cpu, param, 0) {
+                HANDLE_FEATURES => {
+                    self.features_pos = field(cpu, param, 1) as usize;
+                    0
+                }
+                _ => FAILED,
+            },
+            SYS_FLEN => match field(cpu, param, 0) {
+                HANDLE_FEATURES => FEATURES.len() as GuestReg,
+                HANDLE_STDIN | HANDLE_STDOUT | HANDLE_STDERR => 0,
+                _ => FAILED,
+            },
+            // Time stands still so a testcase runs the same way every time
+            SYS_CLOCK | SYS_TIME => 0,
+            SYS_ERRNO => self.errno,
+            SYS_GET_CMDLINE => {
+                // Empty command line
+                let buf = field(cpu, param, 0) as GuestAddr;
+                let _ = cpu.write_mem(buf, &[0]);
+                let _ = cpu.write_mem(param.wrapping_add(WORD_BYTES as GuestAddr), &[0u8; WORD_BYTES]);
+                0
+            }
+            SYS_HEAPINFO => {
+                // All zero, the C library falls back to the heap and stack of its linker script
+                let block = field(cpu, param, 0) as GuestAddr;
+                let _ = cpu.write_mem(block, &[0u8; 4 * WORD_BYTES]);
+                0
+            }
+            _ => FAILED,
+        }
+    }
+}
+
+/*
+ * Exit kind of SYS_EXIT and SYS_EXIT_EXTENDED
+ *
+ * SYS_EXIT carries the reason code itself on 32 bit targets, everything else points at the
+ * reason and the exit code. Only a normal exit with code 0 is a clean run
+ * */
+fn exit_kind(cpu: &CPU, op: GuestReg, param: GuestReg) -> ExitKind {
+    let (reason, code) = if op == SYS_EXIT && WORD_BYTES == 4 {
+        (param, 0)
+    } else {
+        (field(cpu, param as GuestAddr, 0), field(cpu, param as GuestAddr, 1))
+    };
+
+    if reason == ADP_STOPPED_APPLICATION_EXIT && code == 0 {
+        ExitKind::Ok
+    } else {
+        ExitKind::Crash
+    }
+}
+
+// Field of a parameter block, 0 if the block can not be read or wraps around the address space
+fn field(cpu: &CPU, block: GuestAddr, index: usize) -> GuestReg {
+    let mut buf = [0u8; WORD_BYTES];
+    if let Some(addr) = block.checked_add((index * WORD_BYTES) as GuestAddr) {
+        let _ = cpu.read_mem(addr, &mut buf);
+    }
+    GuestReg::from_le_bytes(buf)
+}
+
+// String in the firmware, NUL terminated without a length, cut off at MAX_STRING_SIZE
+fn read_string(cpu: &CPU, addr: GuestAddr, len: Option<GuestReg>) -> Vec<u8> {
+    let max = len.map_or(MAX_STRING_SIZE, |len| (len as GuestAddr).min(MAX_STRING_SIZE));
+
+    let mut bytes = Vec::new();
+    for offset in 0..max {
+        let Some(at) = addr.checked_add(offset) else {
+            break;
+        };
+        let mut byte = [0u8; 1];
+        if cpu.read_mem(at, &mut byte).is_err() || (len.is_none() && byte[0] == 0) {
+            break;
+        }
+        bytes.push(byte[0]);
+    }
+    bytes
+}
+
+// Appends console output to the serial capture of this process, see serial.rs
+fn capture(bytes: &[u8]) {
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(capture_path())
+        .and_then(|mut file| file.write_all(bytes));
+
+    if let Err(e) = written {
+        warn!("Failed to capture semihosting output: {e}");
+    }
+}
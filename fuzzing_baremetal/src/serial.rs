@@ -0,0 +1,206 @@
+use std::{borrow::Cow, fs::{self, File, OpenOptions}, io::Read, path::{Path, PathBuf}};
+use libafl::{
+        corpus::Testcase, executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer}, inputs::Input, observers::Observer, Error};
+use libafl_bolts::{tuples::{Handle, Handled, MatchName, MatchNameRef}, Named};
+use regex::bytes::Regex;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+// Chardev the first serial port of the board is connected to
+const CHARDEV_ID: &str = "fuzz-serial";
+
+// Output of a run past this is dropped, a firmware printing in a loop until the timeout fills it quickly
+pub const MAX_OUTPUT_BYTES: u64 = 64 * 1024;
+
+/*
+ * File the serial output of this process goes to
+ *
+ * Every client of the launcher is its own process, so every QEMU gets its own file
+ * */
+pub fn capture_path() -> PathBuf {
+    std::env::temp_dir().join(format!("{}-serial-{}.log", env!("CARGO_PKG_NAME"), std::process::id()))
+}
+
+/*
+ * QEMU command line with the first serial port written into path
+ *
+ * Any -serial already on the command line is replaced. The file is opened in append mode
+ * so the observer can empty it before every run while QEMU keeps writing to it
+ *
+ * @param qemu_args - QEMU command line, the program name comes first
+ * @param path      - file the serial output goes to
+ * */
+pub fn capture_serial(qemu_args: &[String], path: &Path) -> Vec<String> {
+    let mut args = Vec::with_capacity(qemu_args.len() + 4);
+    let mut rest = qemu_args.iter();
+    while let Some(arg) = rest.next() {
+        if arg == "-serial" {
+            if let Some(serial) = rest.next() {
+                info!("-serial {serial} is replaced, the serial output is captured");
+            }
+            continue;
+        }
+        args.push(arg.clone());
+    }
+
+    args.push("-chardev".to_owned());
+    args.push(format!("file,id={CHARDEV_ID},path={},append=on", path.display()));
+    args.push("-serial".to_owned());
+    args.push(format!("chardev:{CHARDEV_ID}"));
+
+    args
+}
+
+/*
+ * Whatever the firmware printed on its first serial port during a run
+ *
+ * @var name   - observer name
+ * @var path   - file QEMU writes the serial output to, see capture_serial
+ * @var output - bytes of the last run, at most MAX_OUTPUT_BYTES
+ * */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerialObserver {
+    name: Cow<'static, str>,
+    path: PathBuf,
+    #[serde(skip)]
+    output: Vec<u8>,
+}
+
+impl SerialObserver {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            name: Cow::Borrowed("serial"),
+            path,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+impl Named for SerialObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Observer<I, S> for SerialObserver {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.output.clear();
+
+        // QEMU appends, so its next write lands at the start of the emptied file
+        OpenOptions::new().write(true).open(&self.path)?.set_len(0)?;
+        Ok(())
+    }
+
+    fn post_exec(&mut self, _state: &mut S, _input: &I, _exit_kind: &ExitKind) -> Result<(), Error> {
+        File::open(&self.path)?.take(MAX_OUTPUT_BYTES).read_to_end(&mut self.output)?;
+        Ok(())
+    }
+}
+
+/*
+ * Solution when the serial output of a run matches one of the patterns, the serial output
+ * of every solution is written next to it as <name>.serial.txt
+ *
+ * Needs nothing but the firmware printing, e.g. ASSERT or panic messages of firmware that
+ * reports failures over its UART and keeps running
+ *
+ * @var observer  - serial observer of the executor
+ * @var patterns  - regexes matched against the raw output
+ * @var crash_dir - where the solutions and their serial output go
+ * @var output    - serial output of the current execution
+ * @var matched   - pattern the current execution matched
+ * */
+#[derive(Debug)]
+pub struct SerialFeedback {
+    observer: Handle<SerialObserver>,
+    patterns: Vec<Regex>,
+    crash_dir: PathBuf,
+    output: Vec<u8>,
+    matched: Option<String>,
+}
+
+impl SerialFeedback {
+    pub fn new(observer: &SerialObserver, patterns: Vec<Regex>, crash_dir: PathBuf) -> Self {
+        Self {
+            observer: observer.handle(),
+            patterns,
+            crash_dir,
+            output: Vec::new(),
+            matched: None,
+        }
+    }
+}
+
+impl Named for SerialFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SerialFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for SerialFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for SerialFeedback
+where
+    I: Input,
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.observer)
+            .ok_or_else(|| Error::illegal_state("SerialObserver is missing from the executor"))?;
+
+        self.output = observer.output().to_vec();
+        self.matched = self
+            .patterns
+            .iter()
+            .find(|pattern| pattern.is_match(&self.output))
+            .map(|pattern| pattern.as_str().to_owned());
+
+        Ok(self.matched.is_some())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let output = std::mem::take(&mut self.output);
+        let matched = self.matched.take();
+        if output.is_empty() {
+            return Ok(());
+        }
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+
+        // Same name the solution gets in the crash directory
+        let name = input.generate_name(None);
+        fs::write(self.crash_dir.join(format!("{name}.serial.txt")), &output)?;
+        if let Some(pattern) = matched {
+            info!("Serial output of {name} matched '{pattern}'");
+        }
+
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.output.clear();
+        self.matched = None;
+        Ok(())
+    }
+}
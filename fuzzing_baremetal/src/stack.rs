@@ -0,0 +1,334 @@
+use std::{borrow::Cow, fs, marker::PhantomData, ops::Range, path::PathBuf, sync::Mutex};
+use libafl::{
+        corpus::Testcase, events::{Event, EventFirer}, executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer}, inputs::Input,
+        monitors::stats::{AggregatorOps, UserStats, UserStatsValue}, Error, HasMetadata};
+use libafl_bolts::{impl_serdeany, Named};
+use libafl_qemu::{elf::EasyElf, GuestAddr, Qemu, Regs};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::report::Symbolizer;
+
+// Linker script symbols bounding the main stack, see mps2_m3.ld
+pub const STACK_LIMIT_SYMBOL: &str = "__StackLimit";
+const STACK_TOP_SYMBOL: &str = "__StackTop";
+
+/*
+ * Where the stack of a run went below __StackLimit first
+ *
+ * @var pc - start of the block SP was found in
+ * @var sp - SP at that block
+ * */
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct StackOverflow {
+    pub pc: GuestAddr,
+    pub sp: GuestAddr,
+}
+
+/*
+ * How deep the main stack of a run went, kept on every testcase
+ *
+ * @var max_depth - bytes below __StackTop the lowest SP of the run was
+ * @var size      - bytes between __StackLimit and __StackTop
+ * @var overflow  - first block SP was below __StackLimit in
+ * */
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct StackUsage {
+    pub max_depth: u64,
+    pub size: u64,
+    pub overflow: Option<StackOverflow>,
+}
+
+impl_serdeany!(StackUsage);
+
+impl StackUsage {
+    pub fn to_text(&self, symbolizer: &Symbolizer) -> String {
+        match self.overflow {
+            Some(overflow) => format!(
+                "Stack overflow: SP {:#X} at {:#X} {}, {} of {} bytes used",
+                overflow.sp,
+                overflow.pc,
+                symbolizer.symbolize(overflow.pc).unwrap_or_default(),
+                self.max_depth,
+                self.size
+            ),
+            None => format!("Stack: {} of {} bytes used", self.max_depth, self.size),
+        }
+    }
+}
+
+/*
+ * __StackLimit to __StackTop of the firmware
+ *
+ * @param elf - firmware image, linked with a script that defines both symbols
+ * */
+pub fn stack_bounds(elf: &EasyElf) -> Range<GuestAddr> {
+    let limit = elf
+        .resolve_symbol(STACK_LIMIT_SYMBOL, 0)
+        .unwrap_or_else(|| panic!("{STACK_LIMIT_SYMBOL} is not in the kernel, the stack check needs it"));
+    let top = elf
+        .resolve_symbol(STACK_TOP_SYMBOL, 0)
+        .unwrap_or_else(|| panic!("{STACK_TOP_SYMBOL} is not in the kernel, the stack check needs it"));
+
+    assert!(limit < top, "{STACK_LIMIT_SYMBOL} {limit:#X} is not below {STACK_TOP_SYMBOL} {top:#X}");
+    limit..top
+}
+
+/*
+ * Stack of the run and what the hooks found
+ *
+ * @var bounds   - __StackLimit to __StackTop, None until the monitor is installed
+ * @var active   - set while a testcase runs
+ * @var min_sp   - lowest SP of the run
+ * @var overflow - first block of the run SP was below the limit in
+ * */
+struct Monitor {
+    bounds: Option<Range<GuestAddr>>,
+    active: bool,
+    min_sp: GuestAddr,
+    overflow: Option<StackOverflow>,
+}
+
+static MONITOR: Mutex<Monitor> = Mutex::new(Monitor {
+    bounds: None,
+    active: false,
+    min_sp: GuestAddr::MAX,
+    overflow: None,
+});
+
+// The id of a block is its start address, handed to the exec hook every time it runs
+unsafe extern "C" fn gen_block(_data: u64, pc: GuestAddr) -> u64 {
+    pc as u64
+}
+
+unsafe extern "C" fn exec_block(_data: u64, id: u64) {
+    let Some(qemu) = Qemu::get() else {
+        return;
+    };
+
+    let mut monitor = MONITOR.lock().unwrap();
+    if !monitor.active {
+        return;
+    }
+    let Some(limit) = monitor.bounds.as_ref().map(|bounds| bounds.start) else {
+        return;
+    };
+    let Ok(sp) = qemu.cpu_from_index(0).read_reg::<GuestAddr>(Regs::Sp) else {
+        return;
+    };
+
+    monitor.min_sp = monitor.min_sp.min(sp);
+    if sp < limit && monitor.overflow.is_none() {
+        monitor.overflow = Some(StackOverflow { pc: id as GuestAddr, sp });
+    }
+}
+
+/*
+ * Follows SP at every block of a run against the bounds from the linker script
+ *
+ * Only the main stack is covered, firmware that switches to task stacks somewhere else
+ * shows up as an overflow. The firmware keeps running, an overflow becomes a solution
+ * once the run is over
+ *
+ * The hooks live for the whole process, every run starts at the top of the stack
+ * */
+pub struct StackMonitor;
+
+impl StackMonitor {
+    pub fn install(qemu: Qemu, bounds: Option<Range<GuestAddr>>) {
+        let Some(bounds) = bounds else {
+            return;
+        };
+
+        info!("Checking SP against the stack {:#X}-{:#X}", bounds.start, bounds.end);
+        MONITOR.lock().unwrap().bounds = Some(bounds);
+
+        qemu.hooks().add_block_hooks(0u64, Some(gen_block), None, Some(exec_block));
+    }
+
+    pub fn start() {
+        let mut monitor = MONITOR.lock().unwrap();
+        monitor.min_sp = GuestAddr::MAX;
+        monitor.overflow = None;
+        monitor.active = monitor.bounds.is_some();
+    }
+
+    // Ends the run, returns how deep its stack went if the monitor is installed
+    pub fn stop() -> Option<StackUsage> {
+        let mut monitor = MONITOR.lock().unwrap();
+        monitor.active = false;
+
+        let bounds = monitor.bounds.clone()?;
+        Some(StackUsage {
+            max_depth: bounds.end.saturating_sub(monitor.min_sp) as u64,
+            size: (bounds.end - bounds.start) as u64,
+            overflow: monitor.overflow.take(),
+        })
+    }
+}
+
+/*
+ * Keeps the stack depth of a run on every testcase that makes it into the corpus
+ *
+ * Never makes a run interesting by itself, the deepest stack any testcase of this client
+ * reached is sent to the monitor as a user stat
+ *
+ * @var usage     - stack of the current execution
+ * @var max_depth - deepest stack seen so far
+ * */
+#[derive(Debug, Default)]
+pub struct StackDepthFeedback {
+    usage: Option<StackUsage>,
+    max_depth: u64,
+}
+
+impl StackDepthFeedback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Named for StackDepthFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("StackDepthFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for StackDepthFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for StackDepthFeedback
+where
+    EM: EventFirer<I, S>,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        self.usage = state.metadata::<StackUsage>().ok().copied();
+
+        if let Some(usage) = self.usage.filter(|usage| usage.max_depth > self.max_depth) {
+            self.max_depth = usage.max_depth;
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: Cow::Borrowed("stack depth"),
+                    value: UserStats::new(UserStatsValue::Number(self.max_depth), AggregatorOps::Max),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        if let Some(usage) = self.usage.take() {
+            testcase.add_metadata(usage);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.usage = None;
+        Ok(())
+    }
+}
+
+/*
+ * Solution when the stack of a run went below __StackLimit, the overflow is described
+ * next to the solution as <name>.stack.txt and its stack usage kept on the testcase
+ *
+ * @var crash_dir  - where the solutions and their descriptions go
+ * @var symbolizer - functions of the kernel
+ * @var usage      - stack of the current execution if it overflowed
+ * */
+#[derive(Debug)]
+pub struct StackOverflowFeedback {
+    crash_dir: PathBuf,
+    symbolizer: Symbolizer,
+    usage: Option<StackUsage>,
+}
+
+impl StackOverflowFeedback {
+    pub fn new(crash_dir: PathBuf, symbolizer: Symbolizer) -> Self {
+        Self {
+            crash_dir,
+            symbolizer,
+            usage: None,
+        }
+    }
+}
+
+impl Named for StackOverflowFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("StackOverflowFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for StackOverflowFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for StackOverflowFeedback
+where
+    I: Input,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        // Left in the state, the depth feedback reads it after the objectives
+        self.usage = state
+            .metadata::<StackUsage>()
+            .ok()
+            .copied()
+            .filter(|usage| usage.overflow.is_some());
+        Ok(self.usage.is_some())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let Some(usage) = self.usage.take() else {
+            return Ok(());
+        };
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+
+        // Same name the solution gets in the crash directory
+        let name = input.generate_name(None);
+        let text = usage.to_text(&self.symbolizer);
+        fs::write(self.crash_dir.join(format!("{name}.stack.txt")), format!("{text}\n"))?;
+        info!("{name}: {text}");
+
+        testcase.add_metadata(usage);
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.usage = None;
+        Ok(())
+    }
+}
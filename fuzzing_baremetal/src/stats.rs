@@ -0,0 +1,91 @@
+use std::{borrow::Cow, marker::PhantomData, time::Duration};
+use libafl::{
+        events::{Event, EventFirer},
+        monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+        stages::{Restartable, Stage}, state::HasExecutions, Error};
+use libafl_bolts::current_time;
+
+// How often the exec/sec of a client is sent to the monitor
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/*
+ * Reports the exec/sec of a client under the name of its strategy
+ *
+ * Every client sends its own rate, the monitor sums them up so campaigns running
+ * different strategies or restore kinds can be compared side by side
+ *
+ * @var name       - user stat the rate is reported under
+ * @var last_time  - when the rate was last reported
+ * @var last_execs - executions at that point, None until the first perform
+ * */
+#[derive(Debug)]
+pub struct ExecRateStage<I> {
+    name: Cow<'static, str>,
+    last_time: Duration,
+    last_execs: Option<u64>,
+    phantom: PhantomData<I>,
+}
+
+impl<I> ExecRateStage<I> {
+    pub fn new(label: &str) -> Self {
+        Self {
+            name: Cow::Owned(format!("exec/sec ({label})")),
+            last_time: current_time(),
+            last_execs: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for ExecRateStage<I>
+where
+    EM: EventFirer<I, S>,
+    S: HasExecutions,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        let execs = *state.executions();
+
+        // A restarted client starts counting from where its state left off
+        let Some(last_execs) = self.last_execs else {
+            self.last_time = now;
+            self.last_execs = Some(execs);
+            return Ok(());
+        };
+
+        let elapsed = now.saturating_sub(self.last_time);
+        if elapsed < REPORT_INTERVAL {
+            return Ok(());
+        }
+
+        let rate = execs.saturating_sub(last_execs) as f64 / elapsed.as_secs_f64();
+
+        self.last_time = now;
+        self.last_execs = Some(execs);
+
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: self.name.clone(),
+                value: UserStats::new(UserStatsValue::Float(rate), AggregatorOps::Sum),
+                phantom: PhantomData,
+            },
+        )
+    }
+}
+
+impl<I, S> Restartable<S> for ExecRateStage<I> {
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
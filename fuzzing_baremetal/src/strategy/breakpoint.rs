@@ -0,0 +1,123 @@
+use libafl::{executors::ExitKind, HasMetadata};
+use libafl_qemu::{breakpoint::Breakpoint, command::{CommandManager, EndCommand, StartCommand},
+                  elf::EasyElf, modules::EmulatorModuleTuple, Emulator, EmulatorDriver,
+                  GuestAddr, GuestPhysAddr, GuestReg, QemuMemoryChunk};
+use log::info;
+
+use crate::{fault::FaultHandlers, input::WordsInput, options::FuzzerOptions, replay::{FinalPc, ReplayMode},
+            semihosting::Semihosting, strategy::{add_fault_breakpoints, run_with_faults, ExecutionStrategy}};
+
+/*
+ * Uses the command system with breakpoints
+ *
+ * A StartCommand on main writes the testcase and snapshots the guest, an EndCommand on
+ * BREAKPOINT ends the run, the firmware itself knows nothing about the fuzzer
+ *
+ * @var input_addr      - buffer in the firmware the testcases are written into
+ * @var main_addr       - where coverage begins
+ * @var breakpoint_addr - where coverage ends
+ * @var max_input_size  - largest testcase written into the firmware in bytes
+ * @var mode            - what to do differently while replaying
+ * @var faults          - fault handlers, a run ending in one of them is a crash
+ * @var semihosting     - answers semihosting calls, a SYS_EXIT ends the run
+ * */
+#[derive(Clone, Debug)]
+pub struct BreakpointStrategy {
+    input_addr: GuestPhysAddr,
+    main_addr: GuestAddr,
+    breakpoint_addr: GuestAddr,
+    max_input_size: usize,
+    mode: ReplayMode,
+    faults: FaultHandlers,
+    semihosting: Semihosting,
+}
+
+impl BreakpointStrategy {
+    pub fn new(elf: &EasyElf, options: &FuzzerOptions) -> Self {
+        // Memory Address to the input buffer where our mutated testcases will get injected into QEMU
+        let input_addr = elf
+            .resolve_symbol(&options.input_symbol, 0)
+            .expect("having trouble finding the input buffer in binary") as GuestPhysAddr;
+        info!("input address: {:#X}", input_addr);
+
+        // Memory Address to the main function in our harness where coverage begins
+        let main_addr = elf
+            .resolve_symbol(&options.main_symbol, 0)
+            .expect("having trouble finding main function in binary");
+        info!("main address: {:#X}", main_addr);
+
+        // Memory Address to the breakpoint where coverage should end
+        let breakpoint_addr = elf
+            .resolve_symbol(&options.breakpoint_symbol, 0)
+            .expect("having trouble finding BREAKPOINT in binary");
+        info!("Break point address: {:#X}", breakpoint_addr);
+
+        Self {
+            input_addr,
+            main_addr,
+            breakpoint_addr,
+            max_input_size: options.max_input_bytes(),
+            mode: ReplayMode::new(options),
+            faults: FaultHandlers::new(elf, options.board),
+            semihosting: Semihosting::new(elf, options),
+        }
+    }
+}
+
+impl<C, CM, ED, ET, S, SM> ExecutionStrategy<C, CM, ED, ET, S, SM> for BreakpointStrategy
+where
+    C: Clone + From<StartCommand> + From<EndCommand>,
+    CM: CommandManager<ED, ET, WordsInput, S, SM, Commands = C>,
+    ED: EmulatorDriver<C, CM, ET, WordsInput, S, SM>,
+    ET: EmulatorModuleTuple<WordsInput, S>,
+    S: HasMetadata + Unpin,
+{
+    fn prepare(&mut self, emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>) {
+        // Set the start point for QEMU
+        emulator.add_breakpoint(
+            Breakpoint::with_command(
+                self.main_addr,
+                StartCommand::new(QemuMemoryChunk::phys(
+                        self.input_addr,
+                        self.max_input_size as GuestReg,
+                        None,
+                ))
+                .into(),
+                true
+            ),
+            true
+        );
+
+        // Set the end point for QEMU
+        emulator.add_breakpoint(
+            Breakpoint::with_command(
+                self.breakpoint_addr,
+                EndCommand::new(Some(ExitKind::Ok)).into(),
+                false
+            ),
+            true
+        );
+
+        // Fault handlers end the run as a crash, semihosting calls are answered
+        add_fault_breakpoints(emulator, &mut self.faults, &mut self.semihosting);
+
+        let devices = emulator.list_devices();
+        info!("Devices: {:?}", devices);
+    }
+
+    fn run(
+        &mut self,
+        emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+        state: &mut S,
+        input: &WordsInput,
+    ) -> ExitKind {
+        let exit_kind = run_with_faults(emulator, state, input, &mut self.faults, &mut self.semihosting, self.mode);
+
+        // A clean run ends on BREAKPOINT unless a SYS_EXIT left its PC already
+        if self.mode.record_pc && exit_kind == ExitKind::Ok && !state.has_metadata::<FinalPc>() {
+            state.add_metadata(FinalPc(self.breakpoint_addr));
+        }
+
+        exit_kind
+    }
+}
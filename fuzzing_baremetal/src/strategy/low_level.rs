@@ -0,0 +1,281 @@
+use std::fmt;
+use clap::ValueEnum;
+use libafl::{executors::ExitKind, HasMetadata};
+use libafl_qemu::{elf::EasyElf, modules::EmulatorModuleTuple, sys::CPUArchState, Emulator,
+                  FastSnapshotPtr, GuestAddr, GuestPhysAddr, GuestReg, QemuExitError, QemuExitReason,
+                  QemuRWError, QemuShutdownCause, Regs};
+use log::info;
+use serde::Deserialize;
+
+use crate::{fault::{CortexMFault, FaultHandlers}, input::WordsInput, irq::{self, IrqInjector},
+            options::FuzzerOptions, replay::{FinalPc, ReplayMode}, report::CrashContext, semihosting::Semihosting,
+            strategy::{run_until_stop, ExecutionStrategy}};
+
+// Name of the vanilla QEMU snapshot used by RestoreKind::SlowSnapshot
+const SLOW_SNAPSHOT_NAME: &str = "start";
+
+/*
+ * How the low level strategy puts the firmware back into its state at main after each run
+ *
+ * cpu-state     - registers only, memory and devices keep whatever the last run left behind
+ * slow-snapshot - vanilla QEMU snapshot, restores everything QEMU knows about the board
+ * fast-snapshot - libafl fast snapshot of memory and devices
+ * none          - persistent mode, only the PC and SP go back to main
+ * */
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestoreKind {
+    CpuState,
+    SlowSnapshot,
+    #[default]
+    FastSnapshot,
+    None,
+}
+
+impl fmt::Display for RestoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestoreKind::CpuState => write!(f, "cpu_state"),
+            RestoreKind::SlowSnapshot => write!(f, "slow_snapshot"),
+            RestoreKind::FastSnapshot => write!(f, "fast_snapshot"),
+            RestoreKind::None => write!(f, "none"),
+        }
+    }
+}
+
+// Whatever was saved at main for the chosen RestoreKind
+#[derive(Clone)]
+enum Snapshot {
+    CpuState(Vec<CPUArchState>),
+    Slow,
+    Fast(FastSnapshotPtr),
+    Persistent { pc: GuestReg, sp: GuestReg },
+}
+
+/*
+ * The low level way to interact with libafl qemu with direct calls to QEMU functions
+ *
+ * @var input_addr      - buffer in the firmware the testcases are written into
+ * @var main_addr       - where coverage begins
+ * @var breakpoint_addr - where coverage ends
+ * @var pc_reg          - program counter of the board, read to tell where a run stopped
+ * @var max_input_size  - largest testcase written into the firmware in bytes
+ * @var irqs            - leading words of a testcase that schedule interrupts
+ * @var irq_stub        - where the code pending the interrupts goes
+ * @var restore         - how the firmware gets back to main after each run
+ * @var mode            - what to do differently while replaying
+ * @var faults          - fault handlers, stopping in one of them is a crash
+ * @var semihosting     - answers semihosting calls, a SYS_EXIT ends the run
+ * @var snap            - state saved at main, restored after every run
+ * */
+#[derive(Clone)]
+pub struct LowLevelStrategy {
+    input_addr: GuestPhysAddr,
+    main_addr: GuestAddr,
+    breakpoint_addr: GuestAddr,
+    pc_reg: Regs,
+    max_input_size: usize,
+    irqs: usize,
+    irq_stub: GuestAddr,
+    restore: RestoreKind,
+    mode: ReplayMode,
+    faults: FaultHandlers,
+    semihosting: Semihosting,
+    snap: Option<Snapshot>,
+}
+
+impl LowLevelStrategy {
+    pub fn new(elf: &EasyElf, options: &FuzzerOptions) -> Self {
+        // Memory Address to the input buffer where our mutated testcases will get injected into QEMU
+        let input_addr = elf
+            .resolve_symbol(&options.input_symbol, 0)
+            .expect("having trouble finding the input buffer in binary") as GuestPhysAddr;
+        info!("input address: {:#X}", input_addr);
+
+        // Memory Address to the main function in our harness where coverage begins
+        let main_addr = elf
+            .resolve_symbol(&options.main_symbol, 0)
+            .expect("having trouble finding main function in binary");
+        info!("main address: {:#X}", main_addr);
+
+        // Memory Address to the breakpoint where coverage should end
+        let breakpoint_addr = elf
+            .resolve_symbol(&options.breakpoint_symbol, 0)
+            .expect("having trouble finding BREAKPOINT in binary");
+        info!("Break point address: {:#X}", breakpoint_addr);
+
+        Self {
+            input_addr,
+            main_addr,
+            breakpoint_addr,
+            pc_reg: options.board.pc,
+            max_input_size: options.max_input_bytes(),
+            irqs: options.irqs,
+            irq_stub: irq::stub_addr(elf),
+            restore: options.restore,
+            mode: ReplayMode::new(options),
+            faults: FaultHandlers::new(elf, options.board),
+            semihosting: Semihosting::new(elf, options),
+            snap: None,
+        }
+    }
+}
+
+impl<C, CM, ED, ET, S, SM> ExecutionStrategy<C, CM, ED, ET, S, SM> for LowLevelStrategy
+where
+    ET: EmulatorModuleTuple<WordsInput, S>,
+    S: HasMetadata + Unpin,
+{
+    fn prepare(&mut self, emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>) {
+        let qemu = emulator.qemu();
+
+        // Boot code is counted too, the schedule only starts with a run
+        if self.irqs > 0 {
+            IrqInjector::install(qemu, self.irq_stub);
+        }
+
+        // The C library makes semihosting calls before main already
+        for addr in self.semihosting.install(qemu) {
+            qemu.set_breakpoint(addr);
+        }
+
+        // Set the start point for QEMU
+        qemu.set_breakpoint(self.main_addr);
+
+        unsafe {
+            match run_until_stop(qemu, &mut self.semihosting) {
+                (Ok(QemuExitReason::Breakpoint(_)), None) => {}
+                _ => panic!("Unexpected QEMU exit."),
+            }
+        }
+
+        qemu.remove_breakpoint(self.main_addr);
+
+        qemu.set_breakpoint(self.breakpoint_addr);
+
+        // Stopping in a fault handler is a crash
+        self.faults.resolve_vectors(qemu);
+        for addr in self.faults.addrs() {
+            qemu.set_breakpoint(addr);
+        }
+
+        let devices = emulator.list_devices();
+        info!("Devices: {:?}", devices);
+
+        info!("Restoring with {}", self.restore);
+
+        self.snap = Some(match self.restore {
+            RestoreKind::CpuState => Snapshot::CpuState(
+                (0..qemu.num_cpus())
+                    .map(|i| qemu.cpu_from_index(i).save_state())
+                    .collect()
+            ),
+            RestoreKind::SlowSnapshot => {
+                qemu.save_snapshot(SLOW_SNAPSHOT_NAME, true);
+                Snapshot::Slow
+            }
+            RestoreKind::FastSnapshot => Snapshot::Fast(qemu.create_fast_snapshot(true)),
+            RestoreKind::None => {
+                let cpu = qemu.cpu_from_index(0);
+                Snapshot::Persistent {
+                    pc: cpu.read_reg(self.pc_reg).expect("Failed to read PC at main"),
+                    sp: cpu.read_reg(Regs::Sp).expect("Failed to read SP at main"),
+                }
+            }
+        });
+    }
+
+    fn run(
+        &mut self,
+        emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+        state: &mut S,
+        input: &WordsInput,
+    ) -> ExitKind {
+        let qemu = emulator.qemu();
+        let (schedule, payload) = irq::split_input(input, self.irqs);
+        let mut buf = payload.as_slice();
+        let len = buf.len();
+
+        unsafe {
+            if len > self.max_input_size {
+                buf = &buf[0..self.max_input_size];
+            }
+
+            qemu.write_phys_mem(self.input_addr, buf);
+            self.semihosting.start(buf);
+
+            IrqInjector::start(qemu, schedule);
+            let (exit, semihosting_exit) = run_until_stop(qemu, &mut self.semihosting);
+            IrqInjector::stop(qemu);
+
+            match exit {
+                Ok(QemuExitReason::Breakpoint(_)) => {}
+                Ok(QemuExitReason::Timeout) => return ExitKind::Timeout,
+                Ok(QemuExitReason::End(QemuShutdownCause::HostSignal(signal))) => {
+                    signal.handle()
+                }
+
+                Err(QemuExitError::UnexpectedExit) => return ExitKind::Crash,
+                e => panic!("Unexpected QEMU exit: {e:?}"),
+            }
+
+            // If the execution stops at any other point than the designated breakpoint or a
+            // clean SYS_EXIT Crash was found
+            let mut pcs = (0..qemu.num_cpus())
+                .map(|i| qemu.cpu_from_index(i))
+                .map(|cpu| -> Result<GuestAddr, QemuRWError> { cpu.read_reg(self.pc_reg) });
+
+            let ret = match semihosting_exit {
+                Some(kind) => kind,
+                None => match pcs
+                    .find(|pc| (self.breakpoint_addr..self.breakpoint_addr + 5).contains(pc.as_ref().unwrap_or(&0)))
+                {
+                    Some(_) => ExitKind::Ok,
+                    None => ExitKind::Crash
+                },
+            };
+
+            // Decode the fault and keep the CPU state for the report before the restore below wipes it
+            let pc: GuestAddr = qemu.cpu_from_index(0).read_reg(self.pc_reg).unwrap_or(0);
+            if let Some(kind) = self.faults.kind_at(pc) {
+                state.add_metadata(CortexMFault::decode(qemu, kind));
+            }
+            if ret == ExitKind::Crash {
+                state.add_metadata(CrashContext::capture(qemu));
+            }
+
+            if self.mode.record_pc {
+                state.add_metadata(FinalPc(pc));
+            }
+
+            // Leave the firmware where it crashed for gdb
+            if ret == ExitKind::Crash && self.mode.stop_on_crash {
+                return ret;
+            }
+
+            match self.snap.as_ref().expect("prepare was not called") {
+                // OPTION 1: restore only the CPU state (registers et. al)
+                Snapshot::CpuState(saved_cpu_states) => {
+                    for (i, s) in saved_cpu_states.iter().enumerate() {
+                        qemu.cpu_from_index(i).restore_state(s);
+                    }
+                }
+
+                // OPTION 2: restore a slow vanilla QEMU snapshot
+                Snapshot::Slow => qemu.load_snapshot(SLOW_SNAPSHOT_NAME, true),
+
+                // OPTION 3: restore a fast devices+mem snapshot
+                Snapshot::Fast(snap) => qemu.restore_fast_snapshot(*snap),
+
+                // OPTION 4: keep everything, just jump back to main
+                Snapshot::Persistent { pc, sp } => {
+                    let cpu = qemu.cpu_from_index(0);
+                    cpu.write_reg(self.pc_reg, *pc).expect("Failed to reset PC");
+                    cpu.write_reg(Regs::Sp, *sp).expect("Failed to reset SP");
+                }
+            }
+
+            ret
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use libafl::{executors::ExitKind, HasMetadata};
+use libafl_qemu::{elf::EasyElf, modules::EmulatorModuleTuple, Emulator, FastSnapshotPtr, GuestAddr,
+                  QemuExitError, QemuExitReason, QemuShutdownCause, Regs};
+use log::info;
+
+use crate::{fault::{CortexMFault, FaultHandlers}, input::WordsInput, irq::{self, IrqInjector},
+            mmio::{MmioFeed, MmioRange},
+            options::FuzzerOptions, replay::{FinalPc, ReplayMode}, report::CrashContext, semihosting::Semihosting,
+            strategy::{run_until_stop, ExecutionStrategy}};
+
+/*
+ * Feeds the testcase through peripheral reads instead of an input buffer
+ *
+ * Every load from one of the MMIO ranges takes the next bytes of the testcase, the
+ * firmware needs no FUZZ_INPUT. A run ends when the firmware wants more input than the
+ * testcase has, at BREAKPOINT if the firmware has one, at a semihosting SYS_EXIT, in a fault
+ * handler or on timeout
+ *
+ * @var main_addr       - where the snapshot is taken and the testcase starts being served
+ * @var breakpoint_addr - where a run ends successfully, None for unmodified firmware
+ * @var ranges          - peripheral registers served from the testcase
+ * @var max_input_size  - largest stream in bytes
+ * @var irqs            - leading words of a testcase that schedule interrupts
+ * @var irq_stub        - where the code pending the interrupts goes
+ * @var mode            - what to do differently while replaying
+ * @var faults          - fault handlers, stopping in one of them is a crash
+ * @var semihosting     - answers semihosting calls, a SYS_EXIT ends the run
+ * @var snap            - state at main, restored after every run
+ * */
+#[derive(Clone)]
+pub struct MmioStrategy {
+    main_addr: GuestAddr,
+    breakpoint_addr: Option<GuestAddr>,
+    ranges: Vec<MmioRange>,
+    max_input_size: usize,
+    irqs: usize,
+    irq_stub: GuestAddr,
+    mode: ReplayMode,
+    faults: FaultHandlers,
+    semihosting: Semihosting,
+    snap: Option<FastSnapshotPtr>,
+}
+
+impl MmioStrategy {
+    pub fn new(elf: &EasyElf, options: &FuzzerOptions) -> Self {
+        // Memory Address to the main function where the firmware starts reading input
+        let main_addr = elf
+            .resolve_symbol(&options.main_symbol, 0)
+            .expect("having trouble finding main function in binary");
+        info!("main address: {:#X}", main_addr);
+
+        let breakpoint_addr = elf.resolve_symbol(&options.breakpoint_symbol, 0);
+        match breakpoint_addr {
+            Some(addr) => info!("Break point address: {:#X}", addr),
+            None => info!("{} not found, runs end when the input runs out", options.breakpoint_symbol),
+        }
+
+        Self {
+            main_addr,
+            breakpoint_addr,
+            ranges: options.mmio.clone(),
+            max_input_size: options.max_input_bytes(),
+            irqs: options.irqs,
+            irq_stub: irq::stub_addr(elf),
+            mode: ReplayMode::new(options),
+            faults: FaultHandlers::new(elf, options.board),
+            semihosting: Semihosting::new(elf, options),
+            snap: None,
+        }
+    }
+}
+
+impl<C, CM, ED, ET, S, SM> ExecutionStrategy<C, CM, ED, ET, S, SM> for MmioStrategy
+where
+    ET: EmulatorModuleTuple<WordsInput, S>,
+    S: HasMetadata + Unpin,
+{
+    fn prepare(&mut self, emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>) {
+        let qemu = emulator.qemu();
+
+        // Boot code is translated with the hooks too, they only serve input once a run started
+        MmioFeed::install(qemu, &self.ranges);
+        if self.irqs > 0 {
+            IrqInjector::install(qemu, self.irq_stub);
+        }
+
+        // The C library makes semihosting calls before main already
+        for addr in self.semihosting.install(qemu) {
+            qemu.set_breakpoint(addr);
+        }
+
+        qemu.set_breakpoint(self.main_addr);
+
+        unsafe {
+            match run_until_stop(qemu, &mut self.semihosting) {
+                (Ok(QemuExitReason::Breakpoint(_)), None) => {}
+                _ => panic!("Unexpected QEMU exit."),
+            }
+        }
+
+        qemu.remove_breakpoint(self.main_addr);
+
+        if let Some(addr) = self.breakpoint_addr {
+            qemu.set_breakpoint(addr);
+        }
+
+        // Stopping in a fault handler is a crash
+        self.faults.resolve_vectors(qemu);
+        for addr in self.faults.addrs() {
+            qemu.set_breakpoint(addr);
+        }
+
+        let devices = emulator.list_devices();
+        info!("Devices: {:?}", devices);
+
+        self.snap = Some(qemu.create_fast_snapshot(true));
+    }
+
+    fn run(
+        &mut self,
+        emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+        state: &mut S,
+        input: &WordsInput,
+    ) -> ExitKind {
+        let qemu = emulator.qemu();
+
+        let (schedule, mut stream) = irq::split_input(input, self.irqs);
+        stream.truncate(self.max_input_size);
+        // The console and the loads read the same stream one after the other, never the same bytes
+        self.semihosting.start_shared();
+        MmioFeed::start(stream);
+        IrqInjector::start(qemu, schedule);
+
+        let (exit, semihosting_exit) = unsafe { run_until_stop(qemu, &mut self.semihosting) };
+        IrqInjector::stop(qemu);
+        let exhausted = MmioFeed::stop(qemu);
+
+        let pc: GuestAddr = qemu.cpu_from_index(0).read_reg(Regs::Pc).unwrap_or(0);
+
+        // Anywhere else than the end of the harness or a load that ran out of input is a crash
+        let stopped = if Some(pc) == self.breakpoint_addr || Some(pc) == exhausted {
+            ExitKind::Ok
+        } else {
+            ExitKind::Crash
+        };
+
+        let ret = match exit {
+            Ok(QemuExitReason::Breakpoint(_)) => stopped,
+            Ok(QemuExitReason::Timeout) => ExitKind::Timeout,
+            Ok(QemuExitReason::End(QemuShutdownCause::HostSignal(signal))) => {
+                signal.handle();
+                stopped
+            }
+
+            Err(QemuExitError::UnexpectedExit) => ExitKind::Crash,
+            e => panic!("Unexpected QEMU exit: {e:?}"),
+        };
+
+        // A SYS_EXIT stops on a breakpoint too, how it ended the run is up to its exit code
+        let ret = semihosting_exit.unwrap_or(ret);
+
+        // Decode the fault and keep the CPU state for the report before the restore below wipes it
+        if let Some(kind) = self.faults.kind_at(pc) {
+            state.add_metadata(CortexMFault::decode(qemu, kind));
+        }
+        if ret == ExitKind::Crash {
+            state.add_metadata(CrashContext::capture(qemu));
+        }
+        if self.mode.record_pc {
+            state.add_metadata(FinalPc(pc));
+        }
+
+        // Leave the firmware where it crashed for gdb
+        if ret == ExitKind::Crash && self.mode.stop_on_crash {
+            return ret;
+        }
+
+        unsafe { qemu.restore_fast_snapshot(self.snap.expect("prepare was not called")) };
+
+        ret
+    }
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+use clap::ValueEnum;
+use libafl::{executors::ExitKind, inputs::HasTargetBytes, HasMetadata};
+use libafl_qemu::{breakpoint::Breakpoint, command::{CommandManager, EndCommand, StartCommand},
+                  elf::EasyElf, modules::EmulatorModuleTuple, Emulator, EmulatorDriver,
+                  EmulatorDriverResult, EmulatorExitResult, GuestAddr, Qemu, QemuExitError, QemuExitReason, Regs};
+use serde::Deserialize;
+
+use crate::{fault::{CortexMFault, FaultHandlers}, hang::HangLoop, icount::InsnCounter, input::WordsInput,
+            irq::IrqInjector, options::FuzzerOptions,
+            replay::{FinalPc, ReplayMode}, report::CrashContext, semihosting::{Semihosting, SemihostingCall},
+            stack::StackMonitor, watch::Watchpoints};
+
+mod breakpoint;
+mod low_level;
+mod mmio;
+mod nyx;
+mod sync_exit;
+
+pub use breakpoint::BreakpointStrategy;
+pub use low_level::{LowLevelStrategy, RestoreKind};
+pub use mmio::MmioStrategy;
+pub use nyx::NyxStrategy;
+pub use sync_exit::SyncExitStrategy;
+
+/*
+ * How the fuzzer drives the firmware, picked at runtime with --strategy
+ *
+ * low-level  - direct calls into QEMU, breakpoints and snapshots are handled by hand
+ * breakpoint - command system triggered by breakpoints on main and BREAKPOINT
+ * sync-exit  - command system triggered by the firmware itself through libafl_qemu.h
+ * nyx        - firmware written against the Nyx hypercall API in nyx_api.h
+ * mmio       - unmodified firmware reading the testcase from its peripherals
+ * */
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrategyKind {
+    LowLevel,
+    #[default]
+    Breakpoint,
+    SyncExit,
+    Nyx,
+    Mmio,
+}
+
+impl StrategyKind {
+    // Label of the strategy in the monitor, low level also names how it restores
+    pub fn label(&self, restore: RestoreKind) -> String {
+        match self {
+            StrategyKind::LowLevel => format!("{self}/{restore}"),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for StrategyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StrategyKind::LowLevel => write!(f, "low_level"),
+            StrategyKind::Breakpoint => write!(f, "breakpoint"),
+            StrategyKind::SyncExit => write!(f, "sync_exit"),
+            StrategyKind::Nyx => write!(f, "nyx"),
+            StrategyKind::Mmio => write!(f, "mmio"),
+        }
+    }
+}
+
+/*
+ * Everything that differs between the ways of running a testcase in the firmware
+ *
+ * @fn prepare - called once after the emulator is built and before the executor takes it over
+ * @fn run     - runs one testcase and rates how the execution ended
+ * */
+pub trait ExecutionStrategy<C, CM, ED, ET, S, SM> {
+    fn prepare(&mut self, emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>);
+
+    fn run(
+        &mut self,
+        emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+        state: &mut S,
+        input: &WordsInput,
+    ) -> ExitKind;
+}
+
+// The strategy picked on the command line
+#[derive(Clone)]
+pub enum Strategy {
+    LowLevel(LowLevelStrategy),
+    Breakpoint(BreakpointStrategy),
+    SyncExit(SyncExitStrategy),
+    Nyx(NyxStrategy),
+    Mmio(MmioStrategy),
+}
+
+impl Strategy {
+    // Resolves whatever the chosen strategy needs from the firmware
+    pub fn new(kind: StrategyKind, elf: &EasyElf, options: &FuzzerOptions) -> Self {
+        match kind {
+            StrategyKind::LowLevel => Strategy::LowLevel(LowLevelStrategy::new(elf, options)),
+            StrategyKind::Breakpoint => Strategy::Breakpoint(BreakpointStrategy::new(elf, options)),
+            StrategyKind::SyncExit => Strategy::SyncExit(SyncExitStrategy::new(elf, options)),
+            StrategyKind::Nyx => Strategy::Nyx(NyxStrategy::new(elf, options)),
+            StrategyKind::Mmio => Strategy::Mmio(MmioStrategy::new(elf, options)),
+        }
+    }
+}
+
+impl<C, CM, ED, ET, S, SM> ExecutionStrategy<C, CM, ED, ET, S, SM> for Strategy
+where
+    C: Clone + From<StartCommand> + From<EndCommand>,
+    CM: CommandManager<ED, ET, WordsInput, S, SM, Commands = C>,
+    ED: EmulatorDriver<C, CM, ET, WordsInput, S, SM>,
+    ET: EmulatorModuleTuple<WordsInput, S>,
+    S: HasMetadata + Unpin,
+{
+    fn prepare(&mut self, emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>) {
+        match self {
+            Strategy::LowLevel(strategy) => strategy.prepare(emulator),
+            Strategy::Breakpoint(strategy) => strategy.prepare(emulator),
+            Strategy::SyncExit(strategy) => strategy.prepare(emulator),
+            Strategy::Nyx(strategy) => strategy.prepare(emulator),
+            Strategy::Mmio(strategy) => strategy.prepare(emulator),
+        }
+    }
+
+    fn run(
+        &mut self,
+        emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+        state: &mut S,
+        input: &WordsInput,
+    ) -> ExitKind {
+        // Every run gets the same instruction budget and starts without a bad write
+        InsnCounter::start();
+        Watchpoints::start();
+        StackMonitor::start();
+        let exit_kind = match self {
+            Strategy::LowLevel(strategy) => strategy.run(emulator, state, input),
+            Strategy::Breakpoint(strategy) => strategy.run(emulator, state, input),
+            Strategy::SyncExit(strategy) => strategy.run(emulator, state, input),
+            Strategy::Nyx(strategy) => strategy.run(emulator, state, input),
+            Strategy::Mmio(strategy) => strategy.run(emulator, state, input),
+        };
+        InsnCounter::stop();
+        if let Some(violation) = Watchpoints::stop() {
+            state.add_metadata(violation);
+        }
+        if let Some(usage) = StackMonitor::stop() {
+            state.add_metadata(usage);
+        }
+
+        // The blocks a timed out run went through last are the loop it hangs in
+        if exit_kind == ExitKind::Timeout {
+            state.add_metadata(HangLoop::sample());
+        }
+
+        exit_kind
+    }
+}
+
+/*
+ * Runs the firmware until it stops for a strategy calling into QEMU by hand
+ *
+ * Stops for an injected interrupt or a semihosting call are handled here and the firmware
+ * goes on. Returns how QEMU stopped and the exit kind of a SYS_EXIT that ended the run
+ * */
+unsafe fn run_until_stop(
+    qemu: Qemu,
+    semihosting: &mut Semihosting,
+) -> (Result<QemuExitReason, QemuExitError>, Option<ExitKind>) {
+    loop {
+        let exit = unsafe { qemu.run() };
+        if !matches!(exit, Ok(QemuExitReason::Breakpoint(_))) {
+            return (exit, None);
+        }
+        if IrqInjector::handle_stop(qemu) {
+            continue;
+        }
+
+        match semihosting.handle_stop(qemu) {
+            Some(SemihostingCall::Handled) => continue,
+            Some(SemihostingCall::Exit(kind)) => return (exit, Some(kind)),
+            None => return (exit, None),
+        }
+    }
+}
+
+/*
+ * Fault breakpoints for the strategies built on the command system
+ *
+ * The handlers get breakpoints without a command so the run comes back here before the
+ * EndCommand restores the snapshot and wipes the fault registers, semihosting calls stop
+ * the run the same way. The exit pad gets its EndCommand later, see run_with_faults
+ * */
+fn add_fault_breakpoints<C, CM, ED, ET, S, SM>(
+    emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+    faults: &mut FaultHandlers,
+    semihosting: &mut Semihosting,
+) where
+    C: Clone + From<EndCommand>,
+    CM: CommandManager<ED, ET, WordsInput, S, SM, Commands = C>,
+    ED: EmulatorDriver<C, CM, ET, WordsInput, S, SM>,
+    ET: EmulatorModuleTuple<WordsInput, S>,
+    S: Unpin,
+{
+    faults.resolve_vectors(emulator.qemu());
+
+    for addr in faults.addrs() {
+        emulator.add_breakpoint(Breakpoint::without_command(addr, false), true);
+    }
+
+    for addr in semihosting.install(emulator.qemu()) {
+        emulator.add_breakpoint(Breakpoint::without_command(addr, false), true);
+    }
+
+    // Fails here instead of in the middle of the first crash
+    faults.exit_pad();
+}
+
+/*
+ * Runs a testcase through the command system, faults are decoded and end up in the state
+ * with the CPU state, semihosting calls are answered until a SYS_EXIT ends the run
+ * */
+fn run_with_faults<C, CM, ED, ET, S, SM>(
+    emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+    state: &mut S,
+    input: &WordsInput,
+    faults: &mut FaultHandlers,
+    semihosting: &mut Semihosting,
+    mode: ReplayMode,
+) -> ExitKind
+where
+    C: Clone + From<EndCommand>,
+    CM: CommandManager<ED, ET, WordsInput, S, SM, Commands = C>,
+    ED: EmulatorDriver<C, CM, ET, WordsInput, S, SM>,
+    ET: EmulatorModuleTuple<WordsInput, S>,
+    S: HasMetadata + Unpin,
+{
+    let qemu = emulator.qemu();
+    semihosting.start(&input.target_bytes());
+
+    let (result, exit) = loop {
+        let result = unsafe { emulator.run(state, input).unwrap() };
+        if !matches!(result, EmulatorDriverResult::ReturnToClient(EmulatorExitResult::Breakpoint(_))) {
+            break (result, None);
+        }
+
+        match semihosting.handle_stop(qemu) {
+            Some(SemihostingCall::Handled) => {}
+            Some(SemihostingCall::Exit(kind)) => break (result, Some(kind)),
+            None => break (result, None),
+        }
+    };
+
+    let fault = match &result {
+        EmulatorDriverResult::ReturnToClient(EmulatorExitResult::Breakpoint(bp)) => faults.kind_at(bp.addr()),
+        _ => None,
+    };
+    let exit_kind = match (fault, exit) {
+        (Some(kind), _) => {
+            state.add_metadata(CortexMFault::decode(qemu, kind));
+            ExitKind::Crash
+        }
+        (None, Some(kind)) => kind,
+        (None, None) => return result.try_into().unwrap(),
+    };
+
+    if exit_kind == ExitKind::Crash {
+        state.add_metadata(CrashContext::capture(qemu));
+    }
+
+    if mode.record_pc {
+        let pc: GuestAddr = qemu.cpu_from_index(0).read_reg(Regs::Pc).unwrap_or(0);
+        state.add_metadata(FinalPc(pc));
+    }
+
+    // Leave the firmware in the fault handler or at the SYS_EXIT for gdb
+    if exit_kind == ExitKind::Crash && mode.stop_on_crash {
+        return exit_kind;
+    }
+
+    // The boot is long over, the reset handler can carry the EndCommand without ending it
+    if faults.arm_exit_pad() {
+        emulator.add_breakpoint(
+            Breakpoint::with_command(
+                faults.exit_pad(),
+                EndCommand::new(Some(ExitKind::Crash)).into(),
+                false
+            ),
+            true
+        );
+    }
+
+    // Leave through the exit pad so the EndCommand restores the snapshot, the exit kind it ends with is dropped
+    qemu.cpu_from_index(0)
+        .write_reg(Regs::Pc, faults.exit_pad())
+        .expect("Failed to move to the exit pad");
+    unsafe { emulator.run(state, input).unwrap() };
+
+    exit_kind
+}
@@ -0,0 +1,323 @@
+use libafl::{executors::ExitKind, inputs::HasTargetBytes, HasMetadata};
+use libafl_bolts::AsSlice;
+use libafl_qemu::{elf::EasyElf, modules::EmulatorModuleTuple, Emulator, FastSnapshotPtr, GuestAddr,
+                  GuestPhysAddr, GuestReg, Qemu, QemuExitError, QemuExitReason, QemuShutdownCause, Regs, CPU};
+use log::{info, warn};
+
+use crate::{board::{ARG_REGS, RETURN_REG}, fault::{CortexMFault, FaultHandlers}, input::WordsInput,
+            options::FuzzerOptions, replay::{FinalPc, ReplayMode}, report::CrashContext,
+            semihosting::{Semihosting, SemihostingCall}, strategy::ExecutionStrategy};
+
+// Function the firmware calls for every hypercall, see kAFL_hypercall in example/include/nyx_api.h
+const HYPERCALL_SYMBOL: &str = "kAFL_hypercall";
+
+// Flag in the firmware that switches its harness over to the Nyx API
+const NYX_SYMBOL: &str = "FUZZ_NYX";
+
+/*
+ * Hypercalls from nyx_api.h the firmware can make
+ *
+ * Anything not listed is answered with 0 and otherwise ignored
+ * */
+const HYPERCALL_KAFL_ACQUIRE: u32 = 0;
+const HYPERCALL_KAFL_GET_PAYLOAD: u32 = 1;
+const HYPERCALL_KAFL_RELEASE: u32 = 4;
+const HYPERCALL_KAFL_SUBMIT_PANIC: u32 = 6;
+const HYPERCALL_KAFL_PANIC: u32 = 8;
+const HYPERCALL_KAFL_KASAN: u32 = 9;
+const HYPERCALL_KAFL_NEXT_PAYLOAD: u32 = 12;
+const HYPERCALL_KAFL_PRINTF: u32 = 13;
+const HYPERCALL_KAFL_USER_FAST_ACQUIRE: u32 = 18;
+const HYPERCALL_KAFL_USER_ABORT: u32 = 20;
+const HYPERCALL_KAFL_PANIC_EXTENDED: u32 = 32;
+const HYPERCALL_KAFL_GET_HOST_CONFIG: u32 = 35;
+
+// host_config_t handed out with GET_HOST_CONFIG
+const NYX_HOST_MAGIC: u32 = 0x4878_794e;
+const NYX_HOST_VERSION: u32 = 2;
+
+// Longest string read for PRINTF, USER_ABORT and PANIC_EXTENDED
+const HPRINTF_MAX_SIZE: u32 = 0x1000;
+
+// What a hypercall means for the run
+enum Hypercall {
+    // Answered, the firmware goes on
+    Handled,
+    // The firmware is ready for the next payload
+    Acquire,
+    // The payload was processed without trouble
+    Release,
+    // The firmware reported a crash, aborted or passed a pointer that can not be written
+    Panic,
+}
+
+/*
+ * Runs firmware written against the Nyx / kAFL hypercall API
+ *
+ * ARM has no vmcall and the Nyx command set of libafl_qemu only exists for x86 targets, so
+ * the firmware calls kAFL_hypercall and a breakpoint on it hands every hypercall to this
+ * strategy. The first ACQUIRE is where the fast snapshot is taken, each run writes the
+ * payload, returns from that ACQUIRE and lasts until RELEASE, the next ACQUIRE or a panic
+ *
+ * @var hypercall_addr - kAFL_hypercall in the firmware
+ * @var flag_addr      - FUZZ_NYX in the firmware, None if it always speaks the Nyx API
+ * @var max_input_size - largest payload in bytes
+ * @var mode           - what to do differently while replaying
+ * @var faults         - fault handlers, stopping in one of them is a crash
+ * @var semihosting    - answers semihosting calls, a SYS_EXIT ends the run
+ * @var payload_addr   - kAFL_payload registered with GET_PAYLOAD
+ * @var panic_addrs    - handlers registered with SUBMIT_PANIC, stopping in one of them is a crash
+ * @var snap           - state at the first ACQUIRE, restored after every run
+ * */
+#[derive(Clone)]
+pub struct NyxStrategy {
+    hypercall_addr: GuestAddr,
+    flag_addr: Option<GuestPhysAddr>,
+    max_input_size: usize,
+    mode: ReplayMode,
+    faults: FaultHandlers,
+    semihosting: Semihosting,
+    payload_addr: Option<GuestAddr>,
+    panic_addrs: Vec<GuestAddr>,
+    snap: Option<FastSnapshotPtr>,
+}
+
+impl NyxStrategy {
+    pub fn new(elf: &EasyElf, options: &FuzzerOptions) -> Self {
+        let hypercall_addr = elf
+            .resolve_symbol(HYPERCALL_SYMBOL, 0)
+            .expect("having trouble finding kAFL_hypercall in binary");
+        info!("{HYPERCALL_SYMBOL} address: {:#X}", hypercall_addr);
+
+        let flag_addr = elf
+            .resolve_symbol(NYX_SYMBOL, 0)
+            .map(|addr| addr as GuestPhysAddr);
+
+        match flag_addr {
+            Some(addr) => info!("{NYX_SYMBOL} address: {:#X}", addr),
+            None => info!("{NYX_SYMBOL} not found, assuming the firmware always uses the Nyx API"),
+        }
+
+        Self {
+            hypercall_addr,
+            flag_addr,
+            max_input_size: options.max_input_bytes(),
+            mode: ReplayMode::new(options),
+            faults: FaultHandlers::new(elf, options.board),
+            semihosting: Semihosting::new(elf, options),
+            payload_addr: None,
+            panic_addrs: Vec::new(),
+            snap: None,
+        }
+    }
+
+    // Answers the hypercall the CPU is stopped at, the firmware is only returned to when it goes on
+    fn handle_hypercall(&mut self, qemu: Qemu) -> Hypercall {
+        let cpu = qemu.cpu_from_index(0);
+        let nr: GuestReg = cpu.read_reg(ARG_REGS[0]).expect("Failed to read the hypercall number");
+        let arg: GuestAddr = cpu.read_reg(ARG_REGS[1]).expect("Failed to read the hypercall argument");
+
+        match nr as u32 {
+            HYPERCALL_KAFL_ACQUIRE | HYPERCALL_KAFL_NEXT_PAYLOAD | HYPERCALL_KAFL_USER_FAST_ACQUIRE => {
+                return Hypercall::Acquire;
+            }
+            HYPERCALL_KAFL_RELEASE => return Hypercall::Release,
+            HYPERCALL_KAFL_PANIC | HYPERCALL_KAFL_KASAN => return Hypercall::Panic,
+            HYPERCALL_KAFL_PANIC_EXTENDED => {
+                warn!(target: "nyx", "panic: {}", read_cstr(&cpu, arg));
+                return Hypercall::Panic;
+            }
+            HYPERCALL_KAFL_USER_ABORT => {
+                warn!(target: "nyx", "abort: {}", read_cstr(&cpu, arg));
+                return Hypercall::Panic;
+            }
+            HYPERCALL_KAFL_GET_PAYLOAD => {
+                info!("Nyx payload buffer: {:#X}", arg);
+                self.payload_addr = Some(arg);
+            }
+            HYPERCALL_KAFL_SUBMIT_PANIC => {
+                let addr = arg & !1;
+                info!("Nyx panic handler: {:#X}", addr);
+                qemu.set_breakpoint(addr);
+                self.panic_addrs.push(addr);
+            }
+            HYPERCALL_KAFL_GET_HOST_CONFIG => {
+                // host_magic, host_version, bitmap_size, ijon_bitmap_size, payload_buffer_size, worker_id
+                let config: Vec<u8> = [NYX_HOST_MAGIC, NYX_HOST_VERSION, 0, 0, self.payload_size() as u32, 0]
+                    .iter()
+                    .flat_map(|field| field.to_le_bytes())
+                    .collect();
+                if cpu.write_mem(arg, &config).is_err() {
+                    warn!(target: "nyx", "host_config_t at {:#X} can not be written", arg);
+                    return Hypercall::Panic;
+                }
+            }
+            HYPERCALL_KAFL_PRINTF => info!(target: "nyx", "{}", read_cstr(&cpu, arg)),
+            _ => {}
+        }
+
+        return_from_hypercall(&cpu);
+        Hypercall::Handled
+    }
+
+    // kAFL_payload is the size as an i32 followed by the data
+    fn payload_size(&self) -> usize {
+        size_of::<i32>() + self.max_input_size
+    }
+}
+
+impl<C, CM, ED, ET, S, SM> ExecutionStrategy<C, CM, ED, ET, S, SM> for NyxStrategy
+where
+    ET: EmulatorModuleTuple<WordsInput, S>,
+    S: HasMetadata + Unpin,
+{
+    fn prepare(&mut self, emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>) {
+        let qemu = emulator.qemu();
+
+        // Nothing ran yet so the flag is there when the harness checks it
+        if let Some(flag_addr) = self.flag_addr {
+            unsafe { qemu.write_phys_mem(flag_addr, &1u32.to_le_bytes()) };
+        }
+
+        qemu.set_breakpoint(self.hypercall_addr);
+        for addr in self.semihosting.install(qemu) {
+            qemu.set_breakpoint(addr);
+        }
+
+        // Boot and answer the setup hypercalls until the firmware asks for its first payload
+        loop {
+            unsafe {
+                match qemu.run() {
+                    Ok(QemuExitReason::Breakpoint(_)) => {}
+                    e => panic!("Firmware did not reach ACQUIRE: {e:?}"),
+                }
+            }
+
+            match self.semihosting.handle_stop(qemu) {
+                Some(SemihostingCall::Handled) => continue,
+                Some(SemihostingCall::Exit(kind)) => panic!("Firmware exited with {kind:?} before reaching ACQUIRE"),
+                None => {}
+            }
+
+            let pc: GuestAddr = qemu.cpu_from_index(0).read_reg(Regs::Pc).unwrap_or(0);
+            if pc != self.hypercall_addr {
+                panic!("Firmware stopped at {:#X} before reaching ACQUIRE", pc);
+            }
+
+            match self.handle_hypercall(qemu) {
+                Hypercall::Handled => {}
+                Hypercall::Acquire => break,
+                _ => panic!("Firmware released or panicked before reaching ACQUIRE"),
+            }
+        }
+
+        assert!(self.payload_addr.is_some(), "Firmware reached ACQUIRE without GET_PAYLOAD");
+
+        // Stopping in a fault handler is a crash
+        self.faults.resolve_vectors(qemu);
+        for addr in self.faults.addrs() {
+            qemu.set_breakpoint(addr);
+        }
+
+        let devices = emulator.list_devices();
+        info!("Devices: {:?}", devices);
+
+        self.snap = Some(qemu.create_fast_snapshot(true));
+    }
+
+    fn run(
+        &mut self,
+        emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+        state: &mut S,
+        input: &WordsInput,
+    ) -> ExitKind {
+        let qemu = emulator.qemu();
+        let cpu = qemu.cpu_from_index(0);
+
+        let target = input.target_bytes();
+        let buf = target.as_slice();
+        let buf = &buf[..buf.len().min(self.max_input_size)];
+
+        let mut payload = (buf.len() as i32).to_le_bytes().to_vec();
+        payload.extend_from_slice(buf);
+        cpu.write_mem(self.payload_addr.expect("prepare was not called"), &payload)
+            .expect("Failed to write the payload");
+        self.semihosting.start(buf);
+
+        // The snapshot sits at the ACQUIRE, the firmware picks up right after it
+        return_from_hypercall(&cpu);
+
+        let ret = loop {
+            unsafe {
+                match qemu.run() {
+                    Ok(QemuExitReason::Breakpoint(_)) => {}
+                    Ok(QemuExitReason::Timeout) => break ExitKind::Timeout,
+                    Ok(QemuExitReason::End(QemuShutdownCause::HostSignal(signal))) => {
+                        signal.handle()
+                    }
+
+                    Err(QemuExitError::UnexpectedExit) => break ExitKind::Crash,
+                    e => panic!("Unexpected QEMU exit: {e:?}"),
+                }
+            }
+
+            match self.semihosting.handle_stop(qemu) {
+                Some(SemihostingCall::Handled) => continue,
+                Some(SemihostingCall::Exit(kind)) => break kind,
+                None => {}
+            }
+
+            let pc: GuestAddr = cpu.read_reg(Regs::Pc).unwrap_or(0);
+            if pc != self.hypercall_addr {
+                // A fault handler or a handler from SUBMIT_PANIC
+                if let Some(kind) = self.faults.kind_at(pc) {
+                    state.add_metadata(CortexMFault::decode(qemu, kind));
+                }
+                break ExitKind::Crash;
+            }
+
+            match self.handle_hypercall(qemu) {
+                Hypercall::Handled => {}
+                Hypercall::Acquire | Hypercall::Release => break ExitKind::Ok,
+                Hypercall::Panic => break ExitKind::Crash,
+            }
+        };
+
+        let pc: GuestAddr = cpu.read_reg(Regs::Pc).unwrap_or(0);
+        if ret == ExitKind::Crash {
+            state.add_metadata(CrashContext::capture(qemu));
+        }
+        if self.mode.record_pc {
+            state.add_metadata(FinalPc(pc));
+        }
+
+        // Leave the firmware where it crashed for gdb
+        if ret == ExitKind::Crash && self.mode.stop_on_crash {
+            return ret;
+        }
+
+        unsafe { qemu.restore_fast_snapshot(self.snap.expect("prepare was not called")) };
+
+        ret
+    }
+}
+
+// Returns 0 from kAFL_hypercall to whoever called it
+fn return_from_hypercall(cpu: &CPU) {
+    let lr: GuestReg = cpu.read_reg(RETURN_REG.1).expect("Failed to read the return address");
+    cpu.write_reg(ARG_REGS[0], 0u32).expect("Failed to write the hypercall result");
+    cpu.write_reg(Regs::Pc, lr & !1).expect("Failed to return from the hypercall");
+}
+
+// NUL terminated string in the firmware, cut off at HPRINTF_MAX_SIZE
+fn read_cstr(cpu: &CPU, addr: GuestAddr) -> String {
+    let mut bytes = Vec::new();
+    for offset in 0..HPRINTF_MAX_SIZE {
+        let mut byte = [0u8; 1];
+        if cpu.read_mem(addr.wrapping_add(offset as GuestAddr), &mut byte).is_err() || byte[0] == 0 {
+            break;
+        }
+        bytes.push(byte[0]);
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,79 @@
+use libafl::{executors::ExitKind, HasMetadata};
+use libafl_qemu::{command::{CommandManager, EndCommand}, elf::EasyElf,
+                  modules::EmulatorModuleTuple, Emulator, EmulatorDriver, GuestPhysAddr};
+use log::info;
+
+use crate::{fault::FaultHandlers, input::WordsInput, options::FuzzerOptions, replay::ReplayMode,
+            semihosting::Semihosting, strategy::{add_fault_breakpoints, run_with_faults, ExecutionStrategy}};
+
+// Flag in the firmware that switches its harness over to libafl_qemu_start_phys / libafl_qemu_end
+const SYNC_EXIT_SYMBOL: &str = "FUZZ_SYNC_EXIT";
+
+/*
+ * Uses the command system with sync exit
+ *
+ * The firmware tells the fuzzer itself where the input lives and when the run is over
+ *
+ * @var flag_addr   - FUZZ_SYNC_EXIT in the firmware, None if it is always in sync exit mode
+ * @var mode        - what to do differently while replaying
+ * @var faults      - fault handlers, a run ending in one of them is a crash
+ * @var semihosting - answers semihosting calls, a SYS_EXIT ends the run
+ * */
+#[derive(Clone, Debug)]
+pub struct SyncExitStrategy {
+    flag_addr: Option<GuestPhysAddr>,
+    mode: ReplayMode,
+    faults: FaultHandlers,
+    semihosting: Semihosting,
+}
+
+impl SyncExitStrategy {
+    pub fn new(elf: &EasyElf, options: &FuzzerOptions) -> Self {
+        let flag_addr = elf
+            .resolve_symbol(SYNC_EXIT_SYMBOL, 0)
+            .map(|addr| addr as GuestPhysAddr);
+
+        match flag_addr {
+            Some(addr) => info!("{SYNC_EXIT_SYMBOL} address: {:#X}", addr),
+            None => info!("{SYNC_EXIT_SYMBOL} not found, assuming the firmware always uses sync exit"),
+        }
+
+        Self {
+            flag_addr,
+            mode: ReplayMode::new(options),
+            faults: FaultHandlers::new(elf, options.board),
+            semihosting: Semihosting::new(elf, options),
+        }
+    }
+}
+
+impl<C, CM, ED, ET, S, SM> ExecutionStrategy<C, CM, ED, ET, S, SM> for SyncExitStrategy
+where
+    C: Clone + From<EndCommand>,
+    CM: CommandManager<ED, ET, WordsInput, S, SM, Commands = C>,
+    ED: EmulatorDriver<C, CM, ET, WordsInput, S, SM>,
+    ET: EmulatorModuleTuple<WordsInput, S>,
+    S: HasMetadata + Unpin,
+{
+    fn prepare(&mut self, emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>) {
+        // Nothing ran yet so the flag ends up in the snapshot the firmware takes on start
+        if let Some(flag_addr) = self.flag_addr {
+            unsafe { emulator.qemu().write_phys_mem(flag_addr, &1u32.to_le_bytes()) };
+        }
+
+        // Fault handlers end the run as a crash, semihosting calls are answered
+        add_fault_breakpoints(emulator, &mut self.faults, &mut self.semihosting);
+
+        let devices = emulator.list_devices();
+        info!("Devices: {:?}", devices);
+    }
+
+    fn run(
+        &mut self,
+        emulator: &mut Emulator<C, CM, ED, ET, WordsInput, S, SM>,
+        state: &mut S,
+        input: &WordsInput,
+    ) -> ExitKind {
+        run_with_faults(emulator, state, input, &mut self.faults, &mut self.semihosting, self.mode)
+    }
+}
@@ -0,0 +1,426 @@
+use std::{borrow::Cow, fmt, fs, ops::Range, path::PathBuf, str::FromStr, sync::Mutex};
+use libafl::{
+        corpus::Testcase, executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer}, inputs::Input, Error, HasMetadata};
+use libafl_bolts::{impl_serdeany, Named};
+use libafl_qemu::{elf::EasyElf, sys::TCGTemp, GuestAddr, MemAccessInfo, Qemu};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{mmio::MmioRange, options::FuzzerOptions, report::Symbolizer, stack::STACK_LIMIT_SYMBOL};
+
+// Returned from a gen hook to leave the access uninstrumented
+const NO_HOOK: u64 = u64::MAX;
+
+/*
+ * Part of the firmware named on the command line
+ *
+ * An address range written like an --mmio one, anything else is a symbol covering its size
+ * */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WatchTarget {
+    Range(MmioRange),
+    Symbol(String),
+}
+
+impl WatchTarget {
+    /*
+     * Addresses the target covers in the firmware
+     *
+     * @param elf - firmware image, symbols have to come with a size
+     * */
+    fn resolve(&self, elf: &EasyElf) -> Range<GuestAddr> {
+        match self {
+            WatchTarget::Range(range) => range.start..range.end,
+            WatchTarget::Symbol(name) => symbol_range(elf, name)
+                .unwrap_or_else(|| panic!("Symbol {name} to watch is not in the kernel or has no size")),
+        }
+    }
+}
+
+impl FromStr for WatchTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("Empty watch target".to_owned());
+        }
+
+        // Symbols never start with a digit
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            s.parse().map(WatchTarget::Range)
+        } else {
+            Ok(WatchTarget::Symbol(s.to_owned()))
+        }
+    }
+}
+
+impl fmt::Display for WatchTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchTarget::Range(range) => write!(f, "{range}"),
+            WatchTarget::Symbol(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/*
+ * Memory no code but the permitted one may write to during a run
+ *
+ * Written as REGION or REGION=CODE,CODE... e.g. data=sort_data,init_data or
+ * 0x20000000+0x100=0x400-0x800, without code the region must not be written at all
+ *
+ * @var region  - watched memory
+ * @var allowed - code that may write it
+ * */
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct WatchSpec {
+    pub region: WatchTarget,
+    pub allowed: Vec<WatchTarget>,
+}
+
+impl FromStr for WatchSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (region, allowed) = match s.split_once('=') {
+            Some((region, allowed)) => (
+                region.parse()?,
+                allowed.split(',').map(str::parse).collect::<Result<_, _>>()?,
+            ),
+            None => (s.parse()?, Vec::new()),
+        };
+
+        Ok(Self { region, allowed })
+    }
+}
+
+impl TryFrom<String> for WatchSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// Start and end of a symbol, the Thumb bit of functions is dropped
+fn symbol_range(elf: &EasyElf, name: &str) -> Option<Range<GuestAddr>> {
+    let goblin = elf.goblin();
+    goblin
+        .syms
+        .iter()
+        .filter(|sym| sym.st_size > 0)
+        .find(|sym| goblin.strtab.get_at(sym.st_name) == Some(name))
+        .map(|sym| {
+            let start = (sym.st_value as GuestAddr) & !1;
+            start..start + sym.st_size as GuestAddr
+        })
+}
+
+/*
+ * Watched memory resolved against the firmware
+ *
+ * @var name    - what the violation is reported as
+ * @var range   - watched addresses
+ * @var allowed - code that may write them
+ * */
+#[derive(Clone, Debug)]
+pub struct WatchRegion {
+    name: String,
+    range: Range<GuestAddr>,
+    allowed: Vec<Range<GuestAddr>>,
+}
+
+impl WatchRegion {
+    // Every --watch plus the input guards and the stack canary
+    pub fn resolve_all(elf: &EasyElf, options: &FuzzerOptions) -> Vec<Self> {
+        let mut regions: Vec<Self> = options
+            .watch
+            .iter()
+            .map(|spec| Self {
+                name: spec.region.to_string(),
+                range: spec.region.resolve(elf),
+                allowed: spec.allowed.iter().map(|code| code.resolve(elf)).collect(),
+            })
+            .collect();
+
+        // The bytes next to the buffer must be otherwise unused for the guards to mean anything
+        if options.input_guard > 0 {
+            let input = symbol_range(elf, &options.input_symbol)
+                .unwrap_or_else(|| panic!("{} has no size, it cannot be guarded", options.input_symbol));
+            let guard = options.input_guard as GuestAddr;
+            let below = input.start.checked_sub(guard).unwrap_or_else(|| {
+                panic!("--input-guard {guard} reaches below address 0 from {} at {:#X}", options.input_symbol, input.start)
+            });
+            let above = input.end.checked_add(guard).unwrap_or_else(|| {
+                panic!("--input-guard {guard} reaches past the address space from {} at {:#X}", options.input_symbol, input.end)
+            });
+            regions.push(Self {
+                name: format!("guard below {}", options.input_symbol),
+                range: below..input.start,
+                allowed: Vec::new(),
+            });
+            regions.push(Self {
+                name: format!("guard above {}", options.input_symbol),
+                range: input.end..above,
+                allowed: Vec::new(),
+            });
+        }
+
+        // The stack grows down, nothing writes its lowest bytes unless it overflows
+        if options.stack_canary > 0 {
+            let limit = elf
+                .resolve_symbol(STACK_LIMIT_SYMBOL, 0)
+                .unwrap_or_else(|| panic!("{STACK_LIMIT_SYMBOL} is not in the kernel, the stack canary needs it"));
+            regions.push(Self {
+                name: "stack canary".to_owned(),
+                range: limit..limit.checked_add(options.stack_canary as GuestAddr).unwrap_or_else(|| {
+                    panic!("--stack-canary {} reaches past the address space from {STACK_LIMIT_SYMBOL}", options.stack_canary)
+                }),
+                allowed: Vec::new(),
+            });
+        }
+
+        regions
+    }
+
+    // A write from pc touching any byte of the region it is not allowed to
+    fn violated_by(&self, pc: GuestAddr, addr: GuestAddr, size: usize) -> bool {
+        let end = addr.saturating_add(size as GuestAddr);
+        addr < self.range.end && self.range.start < end && !self.allows(pc)
+    }
+
+    fn allows(&self, pc: GuestAddr) -> bool {
+        self.allowed.iter().any(|code| code.contains(&pc))
+    }
+}
+
+impl fmt::Display for WatchRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:#X}-{:#X}", self.name, self.range.start, self.range.end)?;
+        for code in &self.allowed {
+            write!(f, ", written by {:#X}-{:#X}", code.start, code.end)?;
+        }
+        Ok(())
+    }
+}
+
+/*
+ * First write of a run into a watched region from code that may not write it
+ *
+ * @var region - name of the region
+ * @var pc     - the writing instruction
+ * @var addr   - first byte written
+ * @var size   - bytes written
+ * */
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WatchViolation {
+    pub region: String,
+    pub pc: GuestAddr,
+    pub addr: GuestAddr,
+    pub size: usize,
+}
+
+impl_serdeany!(WatchViolation);
+
+impl WatchViolation {
+    pub fn to_text(&self, symbolizer: &Symbolizer) -> String {
+        format!(
+            "{} byte write to {:#X} in {} from {:#X} {}",
+            self.size,
+            self.addr,
+            self.region,
+            self.pc,
+            symbolizer.symbolize(self.pc).unwrap_or_default()
+        )
+    }
+}
+
+/*
+ * Regions of the run and what the hooks found
+ *
+ * @var regions   - watched memory
+ * @var active    - set while a testcase runs, the boot code initialises memory freely
+ * @var violation - first bad write of the run
+ * */
+struct Watch {
+    regions: Vec<WatchRegion>,
+    active: bool,
+    violation: Option<WatchViolation>,
+}
+
+static WATCH: Mutex<Watch> = Mutex::new(Watch {
+    regions: Vec::new(),
+    active: false,
+    violation: None,
+});
+
+// Code allowed to write every region never gets a hook
+unsafe extern "C" fn gen_write(_data: u64, pc: GuestAddr, _addr: *mut TCGTemp, _info: MemAccessInfo) -> u64 {
+    let watch = WATCH.lock().unwrap();
+    if watch.regions.iter().all(|region| region.allows(pc)) {
+        NO_HOOK
+    } else {
+        pc as u64
+    }
+}
+
+fn on_write(pc: GuestAddr, addr: GuestAddr, size: usize) {
+    let mut watch = WATCH.lock().unwrap();
+    if !watch.active || watch.violation.is_some() {
+        return;
+    }
+
+    if let Some(region) = watch.regions.iter().find(|region| region.violated_by(pc, addr, size)) {
+        watch.violation = Some(WatchViolation { region: region.name.clone(), pc, addr, size });
+    }
+}
+
+unsafe extern "C" fn exec_write1(_data: u64, _id: u64, pc: GuestAddr, addr: GuestAddr) {
+    on_write(pc, addr, 1);
+}
+
+unsafe extern "C" fn exec_write2(_data: u64, _id: u64, pc: GuestAddr, addr: GuestAddr) {
+    on_write(pc, addr, 2);
+}
+
+unsafe extern "C" fn exec_write4(_data: u64, _id: u64, pc: GuestAddr, addr: GuestAddr) {
+    on_write(pc, addr, 4);
+}
+
+unsafe extern "C" fn exec_write8(_data: u64, _id: u64, pc: GuestAddr, addr: GuestAddr) {
+    on_write(pc, addr, 8);
+}
+
+unsafe extern "C" fn exec_write_n(_data: u64, _id: u64, pc: GuestAddr, addr: GuestAddr, size: usize) {
+    on_write(pc, addr, size);
+}
+
+/*
+ * Watches memory for writes from code that has no business writing it
+ *
+ * A poor man's sanitizer, bare-metal firmware has no MMU to fault on a stray write. The
+ * firmware keeps running, the run becomes a solution once it is over
+ *
+ * The hooks live for the whole process, every run starts without a violation
+ * */
+pub struct Watchpoints;
+
+impl Watchpoints {
+    // Has to happen before the firmware boots, writes translated earlier are never seen
+    pub fn install(qemu: Qemu, regions: Vec<WatchRegion>) {
+        if regions.is_empty() {
+            return;
+        }
+
+        for region in &regions {
+            info!("Watching {region}");
+        }
+        WATCH.lock().unwrap().regions = regions;
+
+        qemu.hooks().add_write_hooks(
+            0u64,
+            Some(gen_write),
+            Some(exec_write1),
+            Some(exec_write2),
+            Some(exec_write4),
+            Some(exec_write8),
+            Some(exec_write_n),
+        );
+    }
+
+    pub fn start() {
+        let mut watch = WATCH.lock().unwrap();
+        watch.violation = None;
+        watch.active = !watch.regions.is_empty();
+    }
+
+    // Ends the run, returns its first bad write if any
+    pub fn stop() -> Option<WatchViolation> {
+        let mut watch = WATCH.lock().unwrap();
+        watch.active = false;
+        watch.violation.take()
+    }
+}
+
+/*
+ * Solution when a run wrote into a watched region from code that may not write it,
+ * the write is described next to the solution as <name>.watch.txt
+ *
+ * @var crash_dir  - where the solutions and their descriptions go
+ * @var symbolizer - functions of the kernel
+ * @var violation  - bad write of the current execution
+ * */
+#[derive(Debug)]
+pub struct WatchFeedback {
+    crash_dir: PathBuf,
+    symbolizer: Symbolizer,
+    violation: Option<WatchViolation>,
+}
+
+impl WatchFeedback {
+    pub fn new(crash_dir: PathBuf, symbolizer: Symbolizer) -> Self {
+        Self {
+            crash_dir,
+            symbolizer,
+            violation: None,
+        }
+    }
+}
+
+impl Named for WatchFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("WatchFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for WatchFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for WatchFeedback
+where
+    I: Input,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        self.violation = state.metadata_map_mut().remove::<WatchViolation>().map(|violation| *violation);
+        Ok(self.violation.is_some())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let Some(violation) = self.violation.take() else {
+            return Ok(());
+        };
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+
+        // Same name the solution gets in the crash directory
+        let name = input.generate_name(None);
+        let text = violation.to_text(&self.symbolizer);
+        fs::write(self.crash_dir.join(format!("{name}.watch.txt")), format!("{text}\n"))?;
+        info!("{name}: {text}");
+
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.violation = None;
+        Ok(())
+    }
+}
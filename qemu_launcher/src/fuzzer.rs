@@ -0,0 +1,257 @@
+use std::{env, num::NonZero};
+use libafl::{
+        corpus::{Corpus, InMemoryOnDiskCorpus, OnDiskCorpus},
+        events::{ClientDescription, SendExiting}, feedback_or,
+        feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback, TimeoutFeedback}, generators::RandPrintablesGenerator,
+        inputs::BytesInput, mutators::{havoc_mutations, tokens_mutations, StdScheduledMutator, Tokens},
+        observers::{CanTrack, HitcountsMapObserver, TimeObserver, VariableMapObserver},
+        schedulers::{IndexesLenTimeMinimizerScheduler, QueueScheduler}, stages::{CalibrationStage, StdMutationalStage},
+        state::{HasCorpus, HasMetadata, StdState}, Error, Fuzzer, StdFuzzer};
+
+use libafl_bolts::{ownedref::OwnedMutSlice, rands::StdRand, tuples::{tuple_list, Merge}};
+use libafl_qemu::{modules::StdEdgeCoverageModule, Emulator, QemuExecutor};
+use libafl_targets::{edges_map_mut_ptr, EDGES_MAP_DEFAULT_SIZE, MAX_EDGES_FOUND};
+
+#[cfg(feature = "simplemgr")]
+use libafl::{events::SimpleEventManager, monitors::SimpleMonitor};
+#[cfg(feature = "simplemgr")]
+use libafl_bolts::core_affinity::CoreId;
+
+#[cfg(not(feature = "simplemgr"))]
+use libafl::{events::{EventConfig, Launcher}, monitors::MultiMonitor};
+#[cfg(not(feature = "simplemgr"))]
+use libafl_bolts::shmem::{ShMemProvider, StdShMemProvider};
+
+#[cfg(feature = "injections")]
+use crate::injections::injection_module;
+
+use crate::{harness::Harness, options::FuzzerOptions};
+
+pub fn fuzz(options: &FuzzerOptions) {
+    let timeout = options.timeout;
+    #[cfg(not(feature = "simplemgr"))]
+    let broker_port = options.port;
+    #[cfg(not(feature = "simplemgr"))]
+    let cores = &options.cores;
+    let corpus_dir = options.queue_dir();
+    let crash_dir = options.crash_dir();
+    let args = options.qemu_args();
+
+    println!("[LOG] Architecture: {}", env!("CPU_TARGET"));
+    println!("[LOG] Command line: {}", env::args().collect::<Vec<_>>().join(" "));
+    println!("[LOG] Seed: {}, pass --seed {} to fuzz the same way again", options.seed, options.seed);
+
+    /*
+     * After broker is set up the qemu launcher will invoke to the client process once
+     * Basically each processes main function
+     *
+     * @param state              - if restarting a worker it carries over else fresh start
+     * @param mgr                - event manager built with multimonitor to handle reporting with the broker
+     * @param client_description - meta data that must be linked, its id sets the seed of the client
+     * */
+    let mut run_client = |state: Option<_>, mut mgr, client_description: ClientDescription| {
+        // Clients with the same seed would all mutate the same way
+        let seed = options.seed.wrapping_add(client_description.id() as u64);
+
+        // Created an observeration channel to watch code coverage
+        let mut edges_observer = unsafe {
+            HitcountsMapObserver::new(VariableMapObserver::from_mut_slice(
+                    "edges",
+                    OwnedMutSlice::from_raw_parts_mut(edges_map_mut_ptr(), EDGES_MAP_DEFAULT_SIZE),
+                    &raw mut MAX_EDGES_FOUND,
+            ))
+            .track_indices()
+        };
+
+        // Created an observation channel to keep track of execution time
+        let time_observer = TimeObserver::new("Time");
+
+        let edge_module = StdEdgeCoverageModule::builder()
+            .map_observer(edges_observer.as_mut())
+            .build()
+            .expect("Failed to intialize coverage map in QEMU");
+
+        // Hooks the injection sinks, their markers are handed to the token mutations below
+        #[cfg(feature = "injections")]
+        let injection_module = injection_module(&options.injections)
+            .expect("Failed to load injections config");
+        #[cfg(feature = "injections")]
+        let injection_tokens = injection_module.tokens.clone();
+
+        #[cfg(feature = "injections")]
+        let emulator_mods = tuple_list!(edge_module, injection_module);
+
+        #[cfg(not(feature = "injections"))]
+        let emulator_mods = tuple_list!(edge_module);
+
+        // Initialize user mode QEMU with the target and its arguments
+        let emu = Emulator::empty()
+            .qemu_parameters(args.clone())
+            .modules(emulator_mods)
+            .build()
+            .expect("Failed to call QEMU emulator");
+
+        // Run the target up to the harness entry
+        let target = Harness::init(emu.qemu(), &options.entry)
+            .expect("Failed to find the harness in the target");
+
+        // Harness calling the LLVM-style harness inside the target
+        let mut harness = |
+            _emulator: &mut Emulator<_,_,_,_,_,_,_,>,
+            _state: &mut _,
+            input: &BytesInput| target.run(input);
+
+        // Feedback to rate the interestingness of an input
+        // Can eitheir be a slower executions or a new coverage
+        let mut feedback = feedback_or!(
+            MaxMapFeedback::new(&edges_observer),
+            TimeFeedback::new(&time_observer),
+        );
+
+        // Objective to rate what is a solution
+        // A solution can eitheir be a timout or a crash
+        let mut objective = feedback_or!(
+            CrashFeedback::new(),
+            TimeoutFeedback::new()
+        );
+
+        // If not restarting state, create a state from scratch
+        let mut state = state.unwrap_or_else(|| {
+            StdState::new(
+                StdRand::with_seed(seed),
+                InMemoryOnDiskCorpus::new(&corpus_dir).unwrap(),
+                OnDiskCorpus::new(&crash_dir).unwrap(),
+                &mut feedback,
+                &mut objective
+            )
+            .expect("Failed to create state")
+        });
+
+        // A minimization + queue policy to grab testcases from the corpus
+        let scheduler =
+            IndexesLenTimeMinimizerScheduler::new(&edges_observer, QueueScheduler::new());
+
+        let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+        // Dictionary for the token mutations
+        if !state.has_metadata::<Tokens>() {
+            let mut tokens = Tokens::new();
+
+            if let Some(tokens_file) = &options.tokens {
+                tokens.add_from_file(tokens_file).expect("Failed to load tokens file");
+            }
+
+            // Injection markers have to reach the sinks verbatim so feed them to the mutator
+            #[cfg(feature = "injections")]
+            for token in &injection_tokens {
+                tokens.add_token(&token.as_bytes().to_vec());
+            }
+
+            println!("[LOG] Loaded {} tokens", tokens.len());
+            state.add_metadata(tokens);
+        }
+
+        // Creating a mutational stage and a calibration stage
+        let mutator = StdScheduledMutator::new(havoc_mutations().merge(tokens_mutations()));
+        let calibration_feedback = MaxMapFeedback::new(&edges_observer);
+
+        let mut stages = tuple_list!(
+            CalibrationStage::new(&calibration_feedback),
+            StdMutationalStage::new(mutator)
+        );
+
+        // Intializing the QEMU in-process executor
+        let mut executor = QemuExecutor::new(
+            emu,
+            &mut harness,
+            tuple_list!(edges_observer, time_observer),
+            &mut fuzzer,
+            &mut state,
+            &mut mgr,
+            timeout
+        )
+        .expect("Failed to start QEMU executor");
+
+        if state.must_load_initial_inputs() {
+            // Seeds plus whatever a previous campaign left in the queue
+            let seed_dirs: Vec<_> = options.input.iter()
+                .chain([&corpus_dir])
+                .filter(|dir| dir.exists())
+                .cloned()
+                .collect();
+
+            state
+                .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &seed_dirs)
+                .expect("Failed to load testcases from the input directories");
+
+            if state.corpus().count() == 0 {
+                let mut generator = RandPrintablesGenerator::new(NonZero::new(32).unwrap());
+                let test_cases = 8;
+                state.generate_initial_inputs_forced(&mut fuzzer, &mut executor, &mut generator, &mut mgr, test_cases)
+                        .expect("Failed to load empty corpus with intial input");
+
+                println!("[LOG] Generated {test_cases} testcases into corpus");
+            }
+
+            println!("[LOG] Corpus Size: {}", state.corpus().count());
+        }
+
+        match options.iterations {
+            Some(iterations) => {
+                fuzzer
+                    .fuzz_loop_for(&mut stages, &mut executor, &mut state, &mut mgr, iterations)
+                    .unwrap();
+                // Tell the launcher this worker is done instead of respawning it
+                mgr.send_exiting()?;
+            }
+            None => fuzzer
+                .fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)
+                .unwrap(),
+        }
+
+        Ok(())
+    };
+
+    // Debug mode: the whole fuzz loop runs in this process, no broker, no fork and no restarts
+    // so the target can be stepped through under gdb or rr
+    #[cfg(feature = "simplemgr")]
+    {
+        if options.log.is_some() {
+            println!("[LOG] --log is ignored with simplemgr, output stays on the terminal");
+        }
+
+        let monitor = SimpleMonitor::new(|msg| println!("[LOG] {msg}"));
+
+        match run_client(None, SimpleEventManager::new(monitor), ClientDescription::new(0, 0, CoreId(0))) {
+            Ok(()) => (),
+            Err(Error::ShuttingDown) => println!("User stopped fuzzing process"),
+            Err(e) => panic!("Failed to run client: {e:?}"),
+        }
+    }
+
+    #[cfg(not(feature = "simplemgr"))]
+    {
+        // Shared Memory allocator so processes can communicate with eachother
+        let shmem_provider = StdShMemProvider::new().expect("Failed to init shared memory");
+
+        // Stats reporter for the broker
+        let monitor = MultiMonitor::new(|msg| println!("[LOG] {msg}"));
+
+        // Build and run launcher
+        match Launcher::builder()
+            .shmem_provider(shmem_provider)
+            .broker_port(broker_port)
+            .configuration(EventConfig::from_build_id())
+            .monitor(monitor)
+            .run_client(&mut run_client)
+            .cores(cores)
+            .stdout_file(options.log.as_ref().and_then(|log| log.to_str()))
+            .build()
+            .launch()
+            {
+                Ok(()) => (),
+                Err(Error::ShuttingDown) => println!("User stopped fuzzing process"),
+                Err(e) => panic!("Failed to run launcher: {e:?}"),
+            }
+    }
+}
@@ -0,0 +1,148 @@
+use libafl::{
+        executors::ExitKind,
+        inputs::{BytesInput, HasTargetBytes}, Error};
+use libafl_bolts::AsSlice;
+use libafl_qemu::{elf::EasyElf, ArchExtras, GuestAddr, GuestReg, MmapPerms, Qemu,
+                  QemuExitError, QemuExitReason, QemuShutdownCause, Regs};
+
+// Largest testcase we are willing to copy into the guest
+pub const MAX_INPUT_SIZE: usize = 1_048_576;
+
+/*
+ * LLVM-style harness living inside the target binary
+ *
+ * @var qemu       - handle to the user mode QEMU running the target
+ * @var input_addr - guest buffer our mutated testcases get copied into
+ * @var pc         - entry of the harness function
+ * @var stack_ptr  - stack pointer at the moment the harness was first entered
+ * @var ret_addr   - where the harness returns to, a breakpoint is placed here
+ * */
+pub struct Harness {
+    qemu: Qemu,
+    input_addr: GuestAddr,
+    pc: GuestAddr,
+    stack_ptr: GuestAddr,
+    ret_addr: GuestAddr,
+}
+
+impl Harness {
+    /*
+     * Runs the target until it enters the harness the first time and records
+     * everything needed to call it again for every testcase
+     *
+     * @param qemu   - user mode QEMU with the target already loaded
+     * @param symbol - name of the harness entry in the target e.g. LLVMFuzzerTestOneInput
+     * */
+    pub fn init(qemu: Qemu, symbol: &str) -> Result<Self, Error> {
+        // Creates a growable byte buffer that contains the binary of the elf file
+        let mut elf_buffer = Vec::new();
+        let elf = EasyElf::from_file(qemu.binary_path(), &mut elf_buffer)?;
+
+        // PIE binaries are relocated so resolve the symbol relative to the load address
+        let entry = elf
+            .resolve_symbol(symbol, qemu.load_addr())
+            .ok_or_else(|| Error::empty_optional(format!("Symbol {symbol} not found in target")))?;
+        println!("[LOG] harness entry {symbol}: {entry:#x}");
+
+        // Let the target run its own initialization until it calls the harness
+        qemu.entry_break(entry);
+
+        let ret_addr: GuestAddr = qemu
+            .read_return_address()
+            .map_err(|e| Error::unknown(format!("Failed to read return address: {e:?}")))?;
+        println!("[LOG] harness return address: {ret_addr:#x}");
+
+        // Coverage ends once the harness returns
+        qemu.set_breakpoint(ret_addr);
+
+        let input_addr = qemu
+            .map_private(0, MAX_INPUT_SIZE, MmapPerms::ReadWrite)
+            .map_err(|e| Error::unknown(format!("Failed to map input buffer: {e:?}")))?;
+        println!("[LOG] input buffer mapped at: {input_addr:#x}");
+
+        let pc: GuestReg = qemu
+            .read_reg(Regs::Pc)
+            .map_err(|e| Error::unknown(format!("Failed to read PC: {e:?}")))?;
+
+        let stack_ptr: GuestAddr = qemu
+            .read_reg(Regs::Sp)
+            .map_err(|e| Error::unknown(format!("Failed to read SP: {e:?}")))?;
+
+        Ok(Self {
+            qemu,
+            input_addr,
+            pc: pc as GuestAddr,
+            stack_ptr,
+            ret_addr,
+        })
+    }
+
+    /*
+     * Copies the testcase into the guest and calls the harness as if the target
+     * called harness(input_addr, len) itself
+     *
+     * @param input - the mutated testcase
+     * */
+    pub fn run(&self, input: &BytesInput) -> ExitKind {
+        self.load(input).expect("Failed to reset harness");
+        self.resume()
+    }
+
+    // Copies the testcase in and rewinds to the harness entry without running it
+    pub fn load(&self, input: &BytesInput) -> Result<(), Error> {
+        let target = input.target_bytes();
+        let mut buf = target.as_slice();
+
+        if buf.len() > MAX_INPUT_SIZE {
+            buf = &buf[0..MAX_INPUT_SIZE];
+        }
+
+        self.reset(buf)
+    }
+
+    // Runs the harness from wherever the target stands until it returns
+    pub fn resume(&self) -> ExitKind {
+        unsafe {
+            match self.qemu.run() {
+                Ok(QemuExitReason::Breakpoint(_)) => ExitKind::Ok,
+                Ok(QemuExitReason::End(QemuShutdownCause::HostSignal(signal))) => {
+                    signal.handle();
+                    panic!("Unexpected signal: {signal:?}");
+                }
+                Err(QemuExitError::UnexpectedExit) => ExitKind::Crash,
+                e => panic!("Unexpected QEMU exit: {e:?}"),
+            }
+        }
+    }
+
+    // Rewinds registers to the harness entry and places the arguments
+    fn reset(&self, buf: &[u8]) -> Result<(), Error> {
+        let len = buf.len() as GuestReg;
+
+        self.qemu
+            .write_mem(self.input_addr, buf)
+            .map_err(|e| Error::unknown(format!("Failed to write input: {e:?}")))?;
+
+        self.qemu
+            .write_reg(Regs::Pc, self.pc)
+            .map_err(|e| Error::unknown(format!("Failed to write PC: {e:?}")))?;
+
+        self.qemu
+            .write_reg(Regs::Sp, self.stack_ptr)
+            .map_err(|e| Error::unknown(format!("Failed to write SP: {e:?}")))?;
+
+        self.qemu
+            .write_return_address(self.ret_addr)
+            .map_err(|e| Error::unknown(format!("Failed to write return address: {e:?}")))?;
+
+        self.qemu
+            .write_function_argument(0, self.input_addr)
+            .map_err(|e| Error::unknown(format!("Failed to write first argument: {e:?}")))?;
+
+        self.qemu
+            .write_function_argument(1, len)
+            .map_err(|e| Error::unknown(format!("Failed to write second argument: {e:?}")))?;
+
+        Ok(())
+    }
+}
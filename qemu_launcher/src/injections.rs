@@ -0,0 +1,32 @@
+use std::path::Path;
+use libafl::Error;
+use libafl_qemu::modules::injections::InjectionModule;
+
+/*
+ * Builds the injection module from the sinks config
+ *
+ * The module hooks every listed function once its library is mapped and aborts
+ * the target when an argument contains one of the match values, which the
+ * CrashFeedback then turns into a solution
+ *
+ * @param config - .yaml/.yml or .toml file, see injections.yaml
+ * */
+pub fn injection_module(config: &Path) -> Result<InjectionModule, Error> {
+    let path = config
+        .to_str()
+        .ok_or_else(|| Error::illegal_argument(format!("Invalid injections path {config:?}")))?;
+
+    let extension = config
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+
+    let module = match extension.as_deref() {
+        Some("yaml" | "yml") => InjectionModule::from_yaml(path)?,
+        Some("toml") => InjectionModule::from_toml(path)?,
+        _ => return Err(Error::illegal_argument(format!("{path} is neither a YAML nor a TOML file"))),
+    };
+
+    println!("[LOG] Loaded injection sinks from {path}");
+    Ok(module)
+}
@@ -1,3 +1,33 @@
-fn main() {
-    println!("Hello, world!");
+#[cfg(target_os = "linux")]
+mod fuzzer;
+
+#[cfg(target_os = "linux")]
+mod harness;
+
+#[cfg(all(target_os = "linux", feature = "injections"))]
+mod injections;
+
+#[cfg(target_os = "linux")]
+mod options;
+
+#[cfg(target_os = "linux")]
+mod rerun;
+
+#[cfg(target_os = "linux")]
+mod version;
+
+#[cfg(target_os = "linux")]
+pub fn main() {
+    use clap::Parser;
+
+    let options = options::FuzzerOptions::parse();
+    match &options.rerun_input {
+        Some(file) => rerun::rerun(&options, file),
+        None => fuzzer::fuzz(&options),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn main() {
+    panic!("QEMU user mode and libafl qemu is only supported on linux");
 }
@@ -0,0 +1,127 @@
+use std::{fmt, path::PathBuf, time::Duration};
+use clap::{Parser, ValueEnum};
+use libafl_bolts::{core_affinity::Cores, current_nanos};
+
+use crate::version::long_version;
+
+/*
+ * Command line of the launcher
+ *
+ * Everything a campaign depends on is set here so the printed command line
+ * is enough to reproduce a run
+ * */
+#[derive(Parser, Debug)]
+#[command(
+    name = format!("qemu_launcher-{}", env!("CPU_TARGET")),
+    version,
+    long_version = long_version(),
+    about = "Binary-only fuzzer for Linux targets running under QEMU user mode",
+)]
+pub struct FuzzerOptions {
+    /// Directories with seed testcases, can be given multiple times
+    #[arg(short, long = "input", value_name = "DIR")]
+    pub input: Vec<PathBuf>,
+
+    /// Output directory, the queue and the crashes end up in here
+    #[arg(short, long, value_name = "DIR", default_value = "./output")]
+    pub output: PathBuf,
+
+    /// Cores to run worker processes on e.g. "all", "0-3" or "1,3"
+    #[arg(short, long, default_value = "1", value_parser = Cores::from_cmdline)]
+    pub cores: Cores,
+
+    /// Port of the broker all workers report to
+    #[arg(short = 'p', long = "port", default_value_t = 1337)]
+    pub port: u16,
+
+    /// Maximum time in milliseconds a testcase can run before it counts as a timeout
+    #[arg(short, long, value_name = "MS", default_value = "1000", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// Seed of the random number generator, worker n uses seed + n, random if not given
+    #[arg(long, default_value_t = current_nanos(), hide_default_value = true)]
+    pub seed: u64,
+
+    /// Stop after this many fuzzing iterations instead of running forever
+    #[arg(long)]
+    pub iterations: Option<u64>,
+
+    /// AFL style dictionary used by the token mutations
+    #[arg(long, value_name = "FILE")]
+    pub tokens: Option<PathBuf>,
+
+    /// Redirect the output of the worker processes into this file
+    #[arg(short, long, value_name = "FILE")]
+    pub log: Option<PathBuf>,
+
+    /// Harness symbol inside the target that gets called for every testcase
+    #[arg(short, long, default_value = "LLVMFuzzerTestOneInput")]
+    pub entry: String,
+
+    /// Run this one testcase through the harness instead of fuzzing
+    #[arg(long, value_name = "FILE")]
+    pub rerun_input: Option<PathBuf>,
+
+    /// Open QEMU's gdb stub on this port while rerunning, QEMU waits for gdb at the point --gdb-at picks
+    #[arg(short = 'g', long, value_name = "PORT", requires = "rerun_input")]
+    pub gdb_port: Option<u16>,
+
+    /// Where the rerun stops the target and waits for gdb
+    #[arg(long, value_enum, default_value_t = GdbAt::Crash, requires = "gdb_port")]
+    pub gdb_at: GdbAt,
+
+    /// YAML or TOML file listing the sinks checked for SQL, command and path injections
+    #[cfg(feature = "injections")]
+    #[arg(long, value_name = "FILE", default_value = concat!(env!("CARGO_MANIFEST_DIR"), "/injections.yaml"))]
+    pub injections: PathBuf,
+
+    /// Target binary, its arguments and any QEMU arguments e.g. -- -L /usr/arm-linux-gnueabi ./target
+    #[arg(last = true, required = true, value_name = "QEMU ARGS")]
+    pub args: Vec<String>,
+}
+
+/*
+ * Where a rerun opens the gdb stub of --gdb-port and waits for gdb
+ *
+ * entry - once the testcase is in place and the target sits at the harness entry
+ * crash - when the target receives the signal it dies from, before QEMU aborts
+ * */
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GdbAt {
+    Entry,
+    Crash,
+}
+
+impl fmt::Display for GdbAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GdbAt::Entry => write!(f, "entry"),
+            GdbAt::Crash => write!(f, "crash"),
+        }
+    }
+}
+
+impl FuzzerOptions {
+    // Fuzzer generated inputs are stored here
+    pub fn queue_dir(&self) -> PathBuf {
+        self.output.join("queue")
+    }
+
+    // Solutions are stored here
+    pub fn crash_dir(&self) -> PathBuf {
+        self.output.join("crashes")
+    }
+
+    // Arguments handed over to QEMU, QEMU expects the program name first
+    pub fn qemu_args(&self) -> Vec<String> {
+        let mut args = vec![env!("CARGO_PKG_NAME").to_owned()];
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+fn parse_timeout(src: &str) -> Result<Duration, String> {
+    src.parse::<u64>()
+        .map(Duration::from_millis)
+        .map_err(|e| format!("invalid timeout '{src}': {e}"))
+}
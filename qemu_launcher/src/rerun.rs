@@ -0,0 +1,185 @@
+use std::{ffi::CString, path::{Path, PathBuf}, ptr, sync::Mutex, time::Duration};
+use libafl::{
+        corpus::InMemoryCorpus, events::SimpleEventManager, executors::{Executor, HasObservers},
+        feedbacks::ConstFeedback, inputs::{BytesInput, Input}, monitors::NopMonitor,
+        observers::{CanTrack, HitcountsMapObserver, ObserversTuple, VariableMapObserver},
+        schedulers::QueueScheduler, state::StdState, StdFuzzer};
+use libafl_bolts::{ownedref::OwnedMutSlice, rands::StdRand, tuples::tuple_list};
+use libafl_qemu::{modules::StdEdgeCoverageModule, sys::{gdb_handlesig, gdbserver_start}, Emulator, Qemu,
+                  QemuExecutor};
+use libafl_targets::{edges_map_mut_ptr, EDGES_MAP_DEFAULT_SIZE, MAX_EDGES_FOUND};
+
+#[cfg(feature = "injections")]
+use crate::injections::injection_module;
+
+use crate::{harness::Harness, options::{FuzzerOptions, GdbAt}};
+
+// QEMU user mode options followed by a value, the target is the first argument that is neither
+const QEMU_OPTS_WITH_VALUE: &[&str] = &[
+    "L", "s", "cpu", "E", "U", "0", "R", "B", "d", "D", "dfilter", "p", "g", "r", "seed", "trace", "plugin",
+];
+
+// Timeout of a rerun under gdb, nobody steps through a testcase within --timeout
+const GDB_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/*
+ * What the crash hook needs to know about the rerun
+ *
+ * @var file   - testcase that is running
+ * @var binary - target binary for the printed gdb command
+ * @var gdb    - port to open the gdb stub on when the target crashes
+ * */
+struct Crash {
+    file: PathBuf,
+    binary: String,
+    gdb: Option<u16>,
+}
+
+static CRASH: Mutex<Option<Crash>> = Mutex::new(None);
+
+// QEMU aborts right after the crash hooks, the exit kind has to be printed here
+extern "C" fn on_crash(_data: u64, signal: i32) {
+    let Some(crash) = CRASH.lock().unwrap().take() else {
+        return;
+    };
+    println!("[RERUN] {}: Crash, signal {signal}", crash.file.display());
+
+    if let (Some(port), Some(qemu)) = (crash.gdb, Qemu::get()) {
+        wait_for_gdb(qemu, &crash.binary, port, signal);
+    }
+}
+
+/*
+ * Runs one saved testcase through the harness without fuzzing
+ *
+ * QEMU gets the same modules as while fuzzing so injection solutions reproduce. With a
+ * gdb port the rerun stops at the harness entry or at the crash, opens the gdb stub there
+ * and waits for gdb-multiarch before it goes on
+ *
+ * @param options - command line of the launcher
+ * @param file    - testcase e.g. one from output/crashes
+ * */
+pub fn rerun(options: &FuzzerOptions, file: &Path) {
+    let input = BytesInput::from_file(file)
+        .unwrap_or_else(|e| panic!("Failed to read testcase {}: {e}", file.display()));
+    let binary = target_binary(&options.args).unwrap_or("<target>").to_owned();
+
+    // Created an observeration channel to watch code coverage
+    let mut edges_observer = unsafe {
+        HitcountsMapObserver::new(VariableMapObserver::from_mut_slice(
+                "edges",
+                OwnedMutSlice::from_raw_parts_mut(edges_map_mut_ptr(), EDGES_MAP_DEFAULT_SIZE),
+                &raw mut MAX_EDGES_FOUND,
+        ))
+        .track_indices()
+    };
+
+    let edge_module = StdEdgeCoverageModule::builder()
+        .map_observer(edges_observer.as_mut())
+        .build()
+        .expect("Failed to intialize coverage map in QEMU");
+
+    #[cfg(feature = "injections")]
+    let emulator_mods = tuple_list!(
+        edge_module,
+        injection_module(&options.injections).expect("Failed to load injections config")
+    );
+
+    #[cfg(not(feature = "injections"))]
+    let emulator_mods = tuple_list!(edge_module);
+
+    let emu = Emulator::empty()
+        .qemu_parameters(options.qemu_args())
+        .modules(emulator_mods)
+        .build()
+        .expect("Failed to call QEMU emulator");
+    let qemu = emu.qemu();
+
+    let target = Harness::init(qemu, &options.entry)
+        .expect("Failed to find the harness in the target");
+
+    let gdb_at = |at: GdbAt| options.gdb_port.filter(|_| options.gdb_at == at);
+    *CRASH.lock().unwrap() = Some(Crash { file: file.to_owned(), binary: binary.clone(), gdb: gdb_at(GdbAt::Crash) });
+    qemu.hooks().add_crash_hooks(0u64, on_crash);
+
+    let entry_port = gdb_at(GdbAt::Entry);
+    let mut harness = |
+        _emulator: &mut Emulator<_,_,_,_,_,_,_,>,
+        _state: &mut _,
+        input: &BytesInput| {
+        target.load(input).expect("Failed to reset harness");
+        if let Some(port) = entry_port {
+            wait_for_gdb(qemu, &binary, port, 0);
+        }
+        target.resume()
+    };
+
+    // Nothing is kept, the fuzzer only exists because the executor wants one
+    let mut feedback = ConstFeedback::new(false);
+    let mut objective = ConstFeedback::new(false);
+
+    let mut state = StdState::new(
+        StdRand::new(),
+        InMemoryCorpus::<BytesInput>::new(),
+        InMemoryCorpus::new(),
+        &mut feedback,
+        &mut objective
+    )
+    .expect("Failed to create state");
+
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+    let mut mgr = SimpleEventManager::new(NopMonitor::new());
+
+    let timeout = if options.gdb_port.is_some() { GDB_TIMEOUT } else { options.timeout };
+    let mut executor = QemuExecutor::new(
+        emu,
+        &mut harness,
+        tuple_list!(edges_observer),
+        &mut fuzzer,
+        &mut state,
+        &mut mgr,
+        timeout
+    )
+    .expect("Failed to start QEMU executor");
+
+    println!("[RERUN] Running {}", file.display());
+    executor.observers_mut().pre_exec_all(&mut state, &input).unwrap();
+    let exit_kind = executor
+        .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+        .expect("Failed to run testcase");
+    executor.observers_mut().post_exec_all(&mut state, &input, &exit_kind).unwrap();
+
+    CRASH.lock().unwrap().take();
+    println!("[RERUN] {}: {exit_kind:?}", file.display());
+}
+
+/*
+ * Opens QEMU's gdb stub where the target stands and blocks until gdb attaches and lets
+ * it go on
+ *
+ * @param signal - signal gdb is told the target stopped with, 0 for none
+ * */
+fn wait_for_gdb(qemu: Qemu, binary: &str, port: u16, signal: i32) {
+    println!("[RERUN] QEMU waits for gdb on port {port}, attach with:");
+    println!("[RERUN]   gdb-multiarch {binary} -ex 'target remote :{port}'");
+
+    let port = CString::new(port.to_string()).unwrap();
+    unsafe {
+        assert!(gdbserver_start(port.as_ptr()) >= 0, "Failed to open the gdb stub on port {port:?}");
+        gdb_handlesig(qemu.cpu_from_index(0).raw_ptr(), signal, ptr::null(), ptr::null_mut(), 0);
+    }
+}
+
+// QEMU options and their values come first, the target binary right after them
+fn target_binary(args: &[String]) -> Option<&str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(option) = arg.strip_prefix("--").or_else(|| arg.strip_prefix('-')) else {
+            return Some(arg);
+        };
+        if QEMU_OPTS_WITH_VALUE.contains(&option) {
+            args.next();
+        }
+    }
+    None
+}
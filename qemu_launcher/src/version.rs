@@ -0,0 +1,52 @@
+/*
+ * Long version string printed by --version
+ *
+ * Everything here is emitted by vergen in build.rs so a report or crash
+ * can always be traced back to the exact build that produced it
+ * */
+pub fn long_version() -> String {
+    let sections = [
+        ("Build", vec![
+            ("Timestamp", env!("VERGEN_BUILD_TIMESTAMP")),
+            ("Cpu Target", env!("CPU_TARGET")),
+        ]),
+        ("Cargo", vec![
+            ("Target Triple", env!("VERGEN_CARGO_TARGET_TRIPLE")),
+            ("Features", env!("VERGEN_CARGO_FEATURES")),
+            ("Debug", env!("VERGEN_CARGO_DEBUG")),
+            ("Opt Level", env!("VERGEN_CARGO_OPT_LEVEL")),
+        ]),
+        ("Git", vec![
+            ("Branch", env!("VERGEN_GIT_BRANCH")),
+            ("Commit", env!("VERGEN_GIT_SHA")),
+            ("Commit Date", env!("VERGEN_GIT_COMMIT_TIMESTAMP")),
+            ("Describe", env!("VERGEN_GIT_DESCRIBE")),
+            ("Dirty", env!("VERGEN_GIT_DIRTY")),
+        ]),
+        ("Rustc", vec![
+            ("Version", env!("VERGEN_RUSTC_SEMVER")),
+            ("Channel", env!("VERGEN_RUSTC_CHANNEL")),
+            ("Host Triple", env!("VERGEN_RUSTC_HOST_TRIPLE")),
+            ("Commit", env!("VERGEN_RUSTC_COMMIT_HASH")),
+            ("LLVM", env!("VERGEN_RUSTC_LLVM_VERSION")),
+        ]),
+        ("Sysinfo", vec![
+            ("Name", env!("VERGEN_SYSINFO_NAME")),
+            ("OS Version", env!("VERGEN_SYSINFO_OS_VERSION")),
+            ("User", env!("VERGEN_SYSINFO_USER")),
+            ("Total Memory", env!("VERGEN_SYSINFO_TOTAL_MEMORY")),
+            ("CPU Vendor", env!("VERGEN_SYSINFO_CPU_VENDOR")),
+            ("CPU Cores", env!("VERGEN_SYSINFO_CPU_CORE_COUNT")),
+            ("CPU Brand", env!("VERGEN_SYSINFO_CPU_BRAND")),
+        ]),
+    ];
+
+    let mut version = String::from(env!("CARGO_PKG_VERSION"));
+    for (section, entries) in sections {
+        version.push_str(&format!("\n\n{section}:"));
+        for (key, value) in entries {
+            version.push_str(&format!("\n  {key:<14} {value}"));
+        }
+    }
+    version
+}